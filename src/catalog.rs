@@ -0,0 +1,713 @@
+extern crate ring;
+
+use self::ring::digest::{Context, SHA256};
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use slideshow::{self, PhotoInfo};
+use WallflowerError;
+
+/// Name of the catalog within the cache directory
+pub const CATALOG_FILE: &str = "catalog.json";
+/// Name of the record of when photos were shown within the cache directory
+pub const SHOWN_FILE: &str = "shown.json";
+
+/// Limits on the size of the cache, enforced after each sync
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Total size of the cached images in bytes
+    pub max_bytes: Option<u64>,
+    /// Number of cached photos
+    pub max_photos: Option<usize>,
+    /// Only report what would be removed
+    pub dry_run: bool,
+}
+
+/// A photo in the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Flickr photo id
+    pub id: String,
+    /// Name of the image within the cache directory
+    pub file: String,
+    /// The sources the photo was in when it was last seen
+    pub sources: Vec<String>,
+    pub info: PhotoInfo,
+    /// Size of the image in bytes
+    pub size: u64,
+    /// Hex encoded SHA-256 digest of the image
+    pub checksum: String,
+    /// When a sync last found the photo in one of the sources
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A photo that couldn't be downloaded, it's tried again by each sync until it succeeds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    /// Why the last attempt failed
+    pub error: String,
+    /// Number of syncs in a row that failed to download it
+    pub attempts: u32,
+    pub last_attempt: DateTime<Utc>,
+}
+
+/// Photos to remove from the cache, see `Catalog::plan_cleanup`
+#[derive(Debug, Default)]
+pub struct Cleanup {
+    /// Photos that weren't in any of the sources in the last sync
+    pub stale: Vec<Entry>,
+    /// Photos evicted to fit the cache limits, least recently shown first
+    pub evicted: Vec<Entry>,
+    /// Files in the cache directory that aren't in the catalog, see `Catalog::orphans`
+    pub orphans: Vec<PathBuf>,
+}
+
+impl Cleanup {
+    pub fn is_empty(&self) -> bool {
+        self.stale.is_empty() && self.evicted.is_empty() && self.orphans.is_empty()
+    }
+
+    pub fn entries(&self) -> Vec<&Entry> {
+        self.stale.iter().chain(self.evicted.iter()).collect()
+    }
+
+    /// Total size of the images to remove, in bytes
+    pub fn size(&self) -> u64 {
+        self.entries().iter().map(|entry| entry.size).sum()
+    }
+
+    /// Print what is removed, or would be in a dry run
+    pub fn report(&self, dry_run: bool) {
+        let action = if dry_run { "would remove" } else { "removing" };
+        for entry in &self.stale {
+            println!("{} -> {}, no longer in any source", entry.file, action);
+        }
+        for entry in &self.evicted {
+            println!("{} -> {}, over the cache limits", entry.file, action);
+        }
+        for path in &self.orphans {
+            println!("{} -> {}, not in the catalog", path.display(), action);
+        }
+        println!(
+            "cleanup: {} {} photos, {} bytes, and {} other files",
+            action,
+            self.stale.len() + self.evicted.len(),
+            self.size(),
+            self.orphans.len()
+        );
+    }
+}
+
+/// Room left in the cache for new photos during a sync
+///
+/// Photos that have been shown make way for new ones, the cleanup evicts them least recently
+/// shown first. Once the photos that haven't been shown fill the cache new photos aren't
+/// downloaded, otherwise the cleanup would only evict them again.
+#[derive(Debug)]
+pub struct Room {
+    photos: Option<usize>,
+    bytes: Option<u64>,
+}
+
+impl Room {
+    /// The room left by the photos in `listed`, the ones found by the sync, that haven't been
+    /// shown yet
+    pub fn new(
+        catalog: &Catalog,
+        config: &CacheConfig,
+        shown: &ShownLog,
+        listed: &HashSet<String>,
+    ) -> Self {
+        let kept = catalog
+            .photos
+            .iter()
+            .filter(|entry| listed.contains(&entry.id) && shown.last_shown(&entry.id).is_none())
+            .collect::<Vec<_>>();
+        let size = kept.iter().map(|entry| entry.size).sum::<u64>();
+
+        Room {
+            photos: config.max_photos.map(|max| max.saturating_sub(kept.len())),
+            bytes: config.max_bytes.map(|max| max.saturating_sub(size)),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.photos == Some(0) || self.bytes == Some(0)
+    }
+
+    /// Set aside room for `photos` photos taking `bytes` bytes, returning false if they don't fit
+    pub fn reserve(&mut self, photos: usize, bytes: u64) -> bool {
+        let fits = self.photos.map_or(true, |room| room >= photos)
+            && self.bytes.map_or(true, |room| room >= bytes);
+        if fits {
+            if let Some(ref mut room) = self.photos {
+                *room -= photos;
+            }
+            if let Some(ref mut room) = self.bytes {
+                *room -= bytes;
+            }
+        }
+        fits
+    }
+
+    /// Give back room set aside by `reserve` for a download that failed
+    pub fn release(&mut self, photos: usize, bytes: u64) {
+        if let Some(ref mut room) = self.photos {
+            *room += photos;
+        }
+        if let Some(ref mut room) = self.bytes {
+            *room += bytes;
+        }
+    }
+}
+
+/// When each photo was last shown, stored in `shown.json`
+///
+/// This is kept separate from the catalog as it's written by the slideshow while the catalog is
+/// written by the sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShownLog {
+    shown: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl ShownLog {
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, WallflowerError> {
+        match File::open(dir.as_ref().join(SHOWN_FILE)) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(ShownLog::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), WallflowerError> {
+        write_atomic(&dir.as_ref().join(SHOWN_FILE), &serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn mark(&mut self, id: &str, at: DateTime<Utc>) {
+        self.shown.insert(id.to_string(), at);
+    }
+
+    pub fn last_shown(&self, id: &str) -> Option<DateTime<Utc>> {
+        self.shown.get(id).cloned()
+    }
+}
+
+/// Index of the photos in the cache directory, stored in `catalog.json`
+///
+/// The slideshow and the cache maintenance work from the catalog rather than the files in the
+/// directory so they see a consistent set of photos. It's replaced atomically when saved.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Catalog {
+    photos: Vec<Entry>,
+    /// Photos that failed to download in the last sync, by id
+    #[serde(default)]
+    failures: BTreeMap<String, Failure>,
+}
+
+impl Catalog {
+    /// Load the catalog of the cache in `dir`
+    ///
+    /// If there's no catalog yet, for example when upgrading from a version without one, it's
+    /// built from the cached images that have their info saved next to them.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, WallflowerError> {
+        let dir = dir.as_ref();
+        match File::open(dir.join(CATALOG_FILE)) {
+            Ok(file) => Ok(serde_json::from_reader(io::BufReader::new(file))?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Catalog::scan(dir),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn scan(dir: &Path) -> Result<Self, WallflowerError> {
+        let mut catalog = Catalog::default();
+        if !dir.is_dir() {
+            return Ok(catalog);
+        }
+
+        for path in slideshow::available_photos(dir)? {
+            let info = match slideshow::load_photo_info(&path) {
+                Some(info) => info,
+                None => continue,
+            };
+            let modified = fs::metadata(&path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|age| Utc.timestamp(age.as_secs() as i64, 0))
+                .unwrap_or_else(|_| Utc::now());
+            catalog.record(dir, &path, info, None, modified)?;
+        }
+
+        Ok(catalog)
+    }
+
+    /// Write the catalog to `dir`, replacing the old one atomically
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<(), WallflowerError> {
+        let mut contents = serde_json::to_vec_pretty(self)?;
+        contents.push(b'\n');
+        write_atomic(&dir.as_ref().join(CATALOG_FILE), &contents)?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.photos
+    }
+
+    /// Total size of the cached images in bytes
+    pub fn size(&self) -> u64 {
+        self.photos.iter().map(|entry| entry.size).sum()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Entry> {
+        self.photos.iter().find(|entry| entry.id == id)
+    }
+
+    /// Paths of the cached images, in the order they were added
+    pub fn paths<P: AsRef<Path>>(&self, dir: P) -> Vec<PathBuf> {
+        self.photos
+            .iter()
+            .map(|entry| dir.as_ref().join(&entry.file))
+            .collect()
+    }
+
+    /// Add or update the entry for the image at `path`, seen in `source` at `seen`
+    ///
+    /// Entries seen again in the same sync collect each of the sources they were in. The checksum
+    /// of an unchanged image is reused rather than read again.
+    pub fn record(
+        &mut self,
+        dir: &Path,
+        path: &Path,
+        info: PhotoInfo,
+        source: Option<&str>,
+        seen: DateTime<Utc>,
+    ) -> Result<(), WallflowerError> {
+        let file = path.strip_prefix(dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned();
+        let size = fs::metadata(path)?.len();
+
+        let index = self.photos.iter().position(|entry| entry.id == info.id);
+        let (digest, mut sources) = match index.map(|index| &self.photos[index]) {
+            Some(entry) => {
+                let digest = if entry.file == file && entry.size == size {
+                    entry.checksum.clone()
+                } else {
+                    checksum(path)?
+                };
+                let sources = if entry.last_seen == seen {
+                    entry.sources.clone()
+                } else {
+                    Vec::new()
+                };
+                (digest, sources)
+            }
+            None => (checksum(path)?, Vec::new()),
+        };
+        if let Some(source) = source {
+            if !sources.iter().any(|name| name == source) {
+                sources.push(source.to_string());
+            }
+        }
+
+        self.failures.remove(&info.id);
+        let entry = Entry {
+            id: info.id.clone(),
+            file,
+            sources,
+            info,
+            size,
+            checksum: digest,
+            last_seen: seen,
+        };
+        match index {
+            Some(index) => self.photos[index] = entry,
+            None => self.photos.push(entry),
+        }
+
+        Ok(())
+    }
+
+    /// Note that downloading photo `id` failed with `error` in the sync at `at`
+    pub fn record_failure(&mut self, id: &str, error: String, at: DateTime<Utc>) {
+        let attempts = self.failures.get(id).map_or(0, |failure| failure.attempts) + 1;
+        self.failures.insert(
+            id.to_string(),
+            Failure {
+                error,
+                attempts,
+                last_attempt: at,
+            },
+        );
+    }
+
+    pub fn failures(&self) -> &BTreeMap<String, Failure> {
+        &self.failures
+    }
+
+    /// Drop the failures that weren't retried by the sync at `synced_at`, because the photo is
+    /// no longer in any source
+    pub fn forget_failures_before(&mut self, synced_at: DateTime<Utc>) {
+        let forgotten = self.failures
+            .iter()
+            .filter(|&(_id, failure)| failure.last_attempt < synced_at)
+            .map(|(id, _failure)| id.clone())
+            .collect::<Vec<_>>();
+        for id in forgotten {
+            self.failures.remove(&id);
+        }
+    }
+
+    /// Drop the entries of images that are no longer in `dir`
+    pub fn remove_missing<P: AsRef<Path>>(&mut self, dir: P) {
+        let dir = dir.as_ref();
+        self.photos.retain(|entry| dir.join(&entry.file).is_file());
+    }
+
+    /// Work out which photos to remove after a complete sync at `synced_at`
+    ///
+    /// Photos that weren't seen by the sync have been deleted, made private or removed from the
+    /// sources. Then the least recently shown photos are evicted until the cache fits `config`.
+    /// Photos that haven't been shown yet are evicted last.
+    pub fn plan_cleanup(
+        &self,
+        synced_at: DateTime<Utc>,
+        config: &CacheConfig,
+        shown: &ShownLog,
+    ) -> Cleanup {
+        let (mut kept, stale): (Vec<&Entry>, Vec<&Entry>) = self.photos
+            .iter()
+            .partition(|entry| entry.last_seen >= synced_at);
+
+        // Shown photos first, oldest first, then the ones that haven't been shown
+        kept.sort_by_key(|entry| {
+            let last_shown = shown.last_shown(&entry.id);
+            (last_shown.is_none(), last_shown)
+        });
+
+        let mut count = kept.len();
+        let mut size = kept.iter().map(|entry| entry.size).sum::<u64>();
+        let mut evicted = Vec::new();
+        for entry in kept {
+            let over_count = config.max_photos.map_or(false, |max| count > max);
+            let over_size = config.max_bytes.map_or(false, |max| size > max);
+            if !over_count && !over_size {
+                break;
+            }
+
+            count -= 1;
+            size -= entry.size;
+            evicted.push(entry.clone());
+        }
+
+        Cleanup {
+            stale: stale.into_iter().cloned().collect(),
+            evicted,
+            orphans: Vec::new(),
+        }
+    }
+
+    /// Images and info files in `dir` that aren't in the catalog
+    ///
+    /// These are left behind when a larger size of a photo replaces a smaller one, or by images
+    /// cached without their info. Partial downloads are kept to resume while the photo is still
+    /// listed under the failures.
+    pub fn orphans<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>, WallflowerError> {
+        let dir = dir.as_ref();
+        let mut known = HashSet::new();
+        for entry in &self.photos {
+            let path = dir.join(&entry.file);
+            known.insert(path.with_extension("json"));
+            known.insert(path);
+        }
+        for name in &[CATALOG_FILE, SHOWN_FILE] {
+            known.insert(dir.join(name));
+        }
+
+        let mut orphans = Vec::new();
+        if !dir.is_dir() {
+            return Ok(orphans);
+        }
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            let cached = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("jpg") | Some("json") => true,
+                // <id>_<secret>_<size>.jpg.part
+                Some("part") => path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.split('_').next())
+                    .map_or(true, |id| !self.failures.contains_key(id)),
+                _ => false,
+            };
+            if cached && path.is_file() && !known.contains(&path) {
+                orphans.push(path);
+            }
+        }
+        orphans.sort();
+
+        Ok(orphans)
+    }
+
+    /// Delete the images in `cleanup` and their info from `dir`, and drop them from the catalog
+    pub fn remove<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        cleanup: &Cleanup,
+    ) -> Result<(), WallflowerError> {
+        let dir = dir.as_ref();
+        for entry in cleanup.entries() {
+            let path = dir.join(&entry.file);
+            for path in &[path.with_extension("json"), path] {
+                match fs::remove_file(path) {
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+                    result => result?,
+                }
+            }
+            self.photos.retain(|photo| photo.id != entry.id);
+        }
+        for path in &cleanup.orphans {
+            match fs::remove_file(path) {
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+                result => result?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write `contents` to a temporary file next to `path` and move it into place, so readers see
+/// either the old contents or the new
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Hex encoded SHA-256 digest of the file at `path`
+pub fn checksum(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buf = [0; 8192];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        context.update(&buf[..len]);
+    }
+
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[test]
+fn test_record_and_save() {
+    let dir = ::std::env::temp_dir().join(format!("wallflower-catalog-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("1_a_h.jpg");
+    File::create(&path).unwrap().write_all(b"abc").unwrap();
+    let info: PhotoInfo = serde_json::from_str(r#"{"id":"1","title":"One","owner":null,"owner_name":null,"date_taken":null,"date_upload":null,"description":null,"latitude":null,"longitude":null,"license":4,"views":null,"exif":null}"#).unwrap();
+
+    let first_sync = Utc.ymd(2018, 9, 1).and_hms(0, 0, 0);
+    let mut catalog = Catalog::default();
+    catalog.record_failure("1", String::from("HTTP status 503"), first_sync);
+    catalog.record_failure("2", String::from("HTTP status 503"), first_sync);
+    catalog.record_failure("2", String::from("no content type"), first_sync);
+    assert_eq!(catalog.failures()["2"].attempts, 2);
+    catalog.record(&dir, &path, info.clone(), Some("photostream"), first_sync).unwrap();
+    catalog.record(&dir, &path, info.clone(), Some("favorites"), first_sync).unwrap();
+    catalog.save(&dir).unwrap();
+
+    let loaded = Catalog::load(&dir).unwrap();
+    let entry = loaded.get("1").unwrap();
+    assert_eq!(entry.file, "1_a_h.jpg");
+    assert_eq!(entry.size, 3);
+    assert_eq!(entry.checksum, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(entry.sources, vec!["photostream", "favorites"]);
+    assert!(!dir.join("catalog.json.tmp").exists());
+    // A successful download clears the failure
+    assert_eq!(loaded.failures().keys().collect::<Vec<_>>(), vec!["2"]);
+
+    // The sources are reset by the next sync
+    let mut catalog = loaded;
+    let second_sync = Utc.ymd(2018, 9, 2).and_hms(0, 0, 0);
+    catalog.record(&dir, &path, info, Some("favorites"), second_sync).unwrap();
+    assert_eq!(catalog.get("1").unwrap().sources, vec!["favorites"]);
+    assert_eq!(catalog.get("1").unwrap().last_seen, second_sync);
+    catalog.forget_failures_before(second_sync);
+    assert!(catalog.failures().is_empty());
+
+    fs::remove_file(&path).unwrap();
+    catalog.remove_missing(&dir);
+    assert!(catalog.entries().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_plan_cleanup() {
+    let sync = Utc.ymd(2018, 9, 2).and_hms(0, 0, 0);
+    let entry = |id: &str, last_seen| Entry {
+        id: id.to_string(),
+        file: format!("{}_a_h.jpg", id),
+        sources: Vec::new(),
+        info: serde_json::from_str(&format!(r#"{{"id":"{0}","title":"{0}"}}"#, id)).unwrap(),
+        size: 100,
+        checksum: String::new(),
+        last_seen,
+    };
+    let catalog = Catalog {
+        photos: vec![
+            entry("1", sync),
+            entry("2", sync),
+            entry("3", sync - ::chrono::Duration::days(1)),
+            entry("4", sync),
+            entry("5", sync),
+        ],
+        ..Catalog::default()
+    };
+    let mut shown = ShownLog::default();
+    shown.mark("1", sync - ::chrono::Duration::hours(1));
+    shown.mark("2", sync - ::chrono::Duration::hours(2));
+    shown.mark("4", sync - ::chrono::Duration::hours(3));
+    let ids = |entries: &[Entry]| entries.iter().map(|entry| entry.id.clone()).collect::<Vec<_>>();
+
+    let cleanup = catalog.plan_cleanup(sync, &CacheConfig::default(), &shown);
+    assert_eq!(ids(&cleanup.stale), vec!["3"]);
+    assert!(cleanup.evicted.is_empty());
+
+    let config = CacheConfig {
+        max_photos: Some(3),
+        ..Default::default()
+    };
+    let cleanup = catalog.plan_cleanup(sync, &config, &shown);
+    assert_eq!(ids(&cleanup.evicted), vec!["4"]);
+
+    // 5 has never been shown so it's kept over 1
+    let config = CacheConfig {
+        max_bytes: Some(150),
+        ..Default::default()
+    };
+    let cleanup = catalog.plan_cleanup(sync, &config, &shown);
+    assert_eq!(ids(&cleanup.evicted), vec!["4", "2", "1"]);
+    assert_eq!(cleanup.size(), 400);
+}
+
+#[test]
+fn test_room() {
+    let sync = Utc.ymd(2018, 9, 2).and_hms(0, 0, 0);
+    let entry = |id: &str| Entry {
+        id: id.to_string(),
+        file: format!("{}_a_h.jpg", id),
+        sources: Vec::new(),
+        info: serde_json::from_str(&format!(r#"{{"id":"{0}","title":"{0}"}}"#, id)).unwrap(),
+        size: 100,
+        checksum: String::new(),
+        last_seen: sync,
+    };
+    let mut catalog = Catalog {
+        photos: vec![entry("1"), entry("2"), entry("3")],
+        ..Catalog::default()
+    };
+    let mut shown = ShownLog::default();
+    shown.mark("1", sync - ::chrono::Duration::hours(2));
+    shown.mark("2", sync - ::chrono::Duration::hours(1));
+    let listed = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<HashSet<_>>();
+
+    // The cache is full but 1 and 2 have been shown, so a new photo takes the place of 1
+    let config = CacheConfig {
+        max_photos: Some(3),
+        ..Default::default()
+    };
+    let mut room = Room::new(&catalog, &config, &shown, &listed(&["1", "2", "3", "4"]));
+    assert!(room.reserve(1, 100));
+    catalog.photos.push(entry("4"));
+    let cleanup = catalog.plan_cleanup(sync, &config, &shown);
+    let evicted = cleanup.evicted.iter().map(|entry| &entry.id).collect::<Vec<_>>();
+    assert_eq!(evicted, vec!["1"]);
+
+    // 2 makes way for one more, after that the photos that haven't been shown fill the cache
+    assert!(room.reserve(1, 100));
+    assert!(room.is_full());
+    assert!(!room.reserve(1, 0));
+
+    // Photos that are no longer listed don't count
+    let config = CacheConfig {
+        max_bytes: Some(250),
+        ..Default::default()
+    };
+    let mut room = Room::new(&catalog, &config, &ShownLog::default(), &listed(&["4"]));
+    assert!(room.reserve(1, 0));
+    assert!(room.reserve(0, 100));
+    assert!(!room.reserve(0, 100));
+    room.release(1, 100);
+    assert!(room.reserve(1, 100));
+
+    let unlimited = CacheConfig::default();
+    assert!(Room::new(&catalog, &unlimited, &shown, &listed(&[])).reserve(1, 1 << 40));
+}
+
+#[test]
+fn test_orphans() {
+    let dir = ::std::env::temp_dir().join(format!("wallflower-orphans-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let names = [
+        "1_a_h.jpg",
+        "1_a_h.json",
+        "1_a_l.jpg",
+        "2_b_h.jpg",
+        "3_c_h.json",
+        "4_d_h.jpg.part",
+        "5_e_h.jpg.part",
+        "catalog.json",
+        "shown.json",
+    ];
+    for name in &names {
+        File::create(dir.join(name)).unwrap().write_all(b"abc").unwrap();
+    }
+    let info: PhotoInfo = serde_json::from_str(r#"{"id":"1","title":"One"}"#).unwrap();
+    let mut catalog = Catalog::default();
+    catalog.record(&dir, &dir.join("1_a_h.jpg"), info, None, Utc::now()).unwrap();
+    catalog.record_failure("4", String::from("received 3 of 10 bytes"), Utc::now());
+
+    // The smaller size replaced by 1_a_h, an image without info, info without an image and the
+    // partial download of a photo that's no longer in a source
+    let orphans = catalog.orphans(&dir).unwrap();
+    assert_eq!(
+        orphans,
+        vec![
+            dir.join("1_a_l.jpg"),
+            dir.join("2_b_h.jpg"),
+            dir.join("3_c_h.json"),
+            dir.join("5_e_h.jpg.part"),
+        ]
+    );
+
+    let cleanup = Cleanup {
+        orphans,
+        ..Cleanup::default()
+    };
+    catalog.remove(&dir, &cleanup).unwrap();
+    assert!(!dir.join("2_b_h.jpg").exists());
+    assert!(dir.join("1_a_h.jpg").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
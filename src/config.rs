@@ -0,0 +1,118 @@
+use serde_json;
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use catalog::CacheConfig;
+use flickr::{Endpoints, Permission};
+use http::HttpConfig;
+use slideshow::{DownloadConfig, Source};
+use weather;
+use WallflowerError;
+
+/// Settings for the frame, read from a JSON file such as:
+///
+/// ```json
+/// {
+///   "display_width": 1920,
+///   "display_height": 1080,
+///   "sources": [
+///     { "type": "photostream" },
+///     { "type": "photoset", "id": "72157626318069415" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Where to sync photos from
+    pub sources: Vec<Source>,
+    /// Resolution of the display, used to size the window and pick which size of each photo to
+    /// download
+    pub display_width: u32,
+    pub display_height: u32,
+    /// Number of times to retry Flickr API calls that fail with a transient error
+    pub max_retries: u32,
+    /// Maximum number of Flickr API calls to make in any hour
+    pub calls_per_hour: u32,
+    pub http: HttpConfig,
+    /// Where to find the Flickr API, only worth changing for testing
+    pub flickr_endpoints: Endpoints,
+    /// BoM JSON observations feed for the weather station to show in the status bar
+    pub observations_url: String,
+    /// How the first run setup receives the authorization from Flickr
+    pub auth_callback: CallbackConfig,
+    /// The access to ask for when authorizing. Write is needed to fave photos and add them to
+    /// the picks album from the frame.
+    pub permission: Permission,
+    /// Album that photos are added to with the P key
+    pub picks_album: String,
+    /// Where to save the access token, `$XDG_CONFIG_HOME/wallflower/flickr-token.json` by
+    /// default
+    pub token_file: Option<PathBuf>,
+    /// File containing the base64 encoded key to encrypt the access token with, used when
+    /// `WALLFLOWER_TOKEN_KEY` is not set
+    pub token_key_file: Option<PathBuf>,
+    /// Limits on the size of the photo cache
+    pub cache: CacheConfig,
+    /// How many photos are downloaded at once, and how fast
+    pub downloads: DownloadConfig,
+    /// Seconds between syncs while the slideshow is running, at least 5 minutes
+    pub sync_interval: u64,
+}
+
+/// Where to listen for the redirect back from Flickr after authorizing the frame
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CallbackConfig {
+    /// Address to listen on
+    pub listen: String,
+    /// Host name or IP address the frame is reachable at from the browser used to authorize it.
+    /// Defaults to the `listen` address, or the LAN address when listening on all interfaces.
+    pub host: Option<String>,
+    /// Seconds to wait for the authorization to be completed before starting again
+    pub timeout: u64,
+}
+
+impl Default for CallbackConfig {
+    fn default() -> Self {
+        CallbackConfig {
+            listen: String::from("0.0.0.0:8910"),
+            host: None,
+            timeout: 10 * 60,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sources: vec![Source::Photostream],
+            display_width: 1366,
+            display_height: 768,
+            max_retries: 3,
+            calls_per_hour: 3600,
+            http: HttpConfig::default(),
+            flickr_endpoints: Endpoints::default(),
+            observations_url: String::from(weather::OBSERVATIONS_URL),
+            auth_callback: CallbackConfig::default(),
+            permission: Permission::default(),
+            picks_album: String::from("Frame picks"),
+            token_file: None,
+            token_key_file: None,
+            cache: CacheConfig::default(),
+            downloads: DownloadConfig::default(),
+            sync_interval: 60 * 60,
+        }
+    }
+}
+
+/// Load the config at `path`, falling back to the defaults if it does not exist
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, WallflowerError> {
+    match File::open(path.as_ref()) {
+        Ok(file) => Ok(serde_json::from_reader(file)?),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(err.into()),
+    }
+}
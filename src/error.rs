@@ -1,7 +1,11 @@
 extern crate reqwest;
 extern crate serde_json;
 
+use chrono;
+use exif;
 use std::{io, num, str};
+use std::time::Duration;
+use http::TransportError;
 use weather::WeatherError;
 
 #[derive(Fail, Debug)]
@@ -13,6 +17,26 @@ pub enum WallflowerError {
     #[fail(display = "JSON error")] JsonError(serde_json::Error),
     #[fail(display = "Graphics error")] GraphicsError,
     #[fail(display = "Weather error")] WeatherError(WeatherError),
+    #[fail(display = "No usable size for photo {}", _0)] NoSuitableSize(String),
+    #[fail(display = "EXIF error")] ExifError(exif::Error),
+    #[fail(display = "The token key must be 32 bytes, base64 encoded")] InvalidTokenKey,
+    #[fail(display = "The access token is encrypted but no key was given")] TokenKeyMissing,
+    #[fail(display = "Unable to decrypt the access token, is the key correct?")] TokenDecryptError,
+    #[fail(display = "Syncing {} needs FLICKR_API_SECRET so the frame can be authorized", _0)]
+    AuthorizationRequired(String),
+    #[fail(display = "Invalid download from {}: {}", url, reason)]
+    InvalidDownload { url: String, reason: String },
+    #[fail(display = "The photo cache is full")] CacheFull,
+}
+
+impl WallflowerError {
+    /// Whether Flickr rejected the access token, usually because the user revoked the app
+    pub fn is_invalid_token(&self) -> bool {
+        match *self {
+            WallflowerError::FlickrError(FlickrError::InvalidToken) => true,
+            _ => false,
+        }
+    }
 }
 
 impl From<str::Utf8Error> for WallflowerError {
@@ -21,6 +45,12 @@ impl From<str::Utf8Error> for WallflowerError {
     }
 }
 
+impl From<exif::Error> for WallflowerError {
+    fn from(err: exif::Error) -> Self {
+        WallflowerError::ExifError(err)
+    }
+}
+
 impl From<FlickrError> for WallflowerError {
     fn from(err: FlickrError) -> Self {
         WallflowerError::FlickrError(err)
@@ -54,11 +84,25 @@ impl From<reqwest::Error> for WallflowerError {
 #[derive(Fail, Debug)]
 pub enum FlickrError {
     #[fail(display = "The request was rejected")] AuthenticationError,
+    #[fail(display = "The app was not authorized within {:?}", _0)] AuthorizationTimeout(Duration),
+    #[fail(display = "Malformed OAuth response: {}", _0)] MalformedOauthResponse(String),
     #[fail(display = "JSON error")] JsonError(serde_json::Error),
     #[fail(display = "I/O error")] IoError(io::Error),
     #[fail(display = "HTTP error")] HttpError(reqwest::Error),
     #[fail(display = "URL error")] UrlError(reqwest::UrlError),
     #[fail(display = "Parse error")] ParseError(num::ParseIntError),
+    #[fail(display = "Parse error")] ParseFloatError(num::ParseFloatError),
+    #[fail(display = "Date parse error")] DateError(chrono::ParseError),
+    #[fail(display = "Flickr API error {}: {}", code, message)] Api { code: u32, message: String },
+    #[fail(display = "The OAuth token is invalid or has been revoked")] InvalidToken,
+    #[fail(display = "The app was not authorized with enough permission")] InsufficientPermissions,
+    #[fail(display = "The API key is invalid")] InvalidApiKey,
+    #[fail(display = "The Flickr API is currently unavailable")] ServiceUnavailable,
+    #[fail(display = "User not found")] UserNotFound,
+    #[fail(display = "The owner has hidden the EXIF data of the photo")] ExifHidden,
+    #[fail(display = "HTTP status {}", status)] HttpStatus { status: u16, retry_after: Option<Duration> },
+    #[fail(display = "The hourly API call budget has been used up, retry after {:?}", retry_after)]
+    BudgetExhausted { retry_after: Duration },
 }
 
 impl From<serde_json::Error> for FlickrError {
@@ -79,6 +123,15 @@ impl From<reqwest::Error> for FlickrError {
     }
 }
 
+impl From<TransportError> for FlickrError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            TransportError::HttpError(err) => FlickrError::HttpError(err),
+            TransportError::IoError(err) => FlickrError::IoError(err),
+        }
+    }
+}
+
 impl From<reqwest::UrlError> for FlickrError {
     fn from(err: reqwest::UrlError) -> Self {
         FlickrError::UrlError(err)
@@ -90,3 +143,15 @@ impl From<num::ParseIntError> for FlickrError {
         FlickrError::ParseError(err)
     }
 }
+
+impl From<num::ParseFloatError> for FlickrError {
+    fn from(err: num::ParseFloatError) -> Self {
+        FlickrError::ParseFloatError(err)
+    }
+}
+
+impl From<chrono::ParseError> for FlickrError {
+    fn from(err: chrono::ParseError) -> Self {
+        FlickrError::DateError(err)
+    }
+}
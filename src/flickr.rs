@@ -1,42 +1,28 @@
-extern crate base64;
-extern crate hmac;
 extern crate percent_encoding;
+extern crate rand;
 extern crate reqwest;
 extern crate serde;
-extern crate sha1;
-extern crate uuid;
 
-use self::hmac::{Hmac, Mac};
-use self::percent_encoding::{EncodeSet, utf8_percent_encode};
+use self::percent_encoding::percent_decode;
+use self::rand::Rng;
 use self::reqwest::Url;
 use self::serde::de::DeserializeOwned;
-use self::sha1::Sha1;
-use self::uuid::Uuid;
 
-use std::io;
-use std::time::SystemTime;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde_json;
 
-use error::FlickrError;
-
-type HmacSha1 = Hmac<Sha1>;
-type FlickrResult<T> = Result<T, FlickrError>;
-
-#[derive(Copy, Clone, Debug)]
-#[allow(non_camel_case_types)]
-struct UNRESERVED_ENCODE_SET;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-impl EncodeSet for UNRESERVED_ENCODE_SET {
-    fn contains(&self, byte: u8) -> bool {
-        if byte.is_ascii_lowercase() || byte.is_ascii_uppercase() || byte.is_ascii_digit() {
-            return false;
-        }
+use error::FlickrError;
+use http::{Request, Response, Transport};
+use oauth1::{self, Signer};
 
-        match byte {
-            b'-' | b'.' | b'_' | b'~' => false,
-            _ => true,
-        }
-    }
-}
+pub type FlickrResult<T> = Result<T, FlickrError>;
 
 pub trait TryFrom<T>: Sized {
     type Error;
@@ -51,6 +37,45 @@ pub enum Stat {
     Fail,
 }
 
+// Every response includes stat, failed responses also include an error code and message:
+// {"stat":"fail","code":1,"message":"Required arguments missing"}
+#[derive(Debug, Deserialize)]
+struct ResponseStatus {
+    stat: Stat,
+    code: Option<u32>,
+    message: Option<String>,
+}
+
+/// Check the stat of an API response body before deserializing it as `T`
+fn parse_response<T: DeserializeOwned>(method: &str, body: &str) -> FlickrResult<T> {
+    let status: ResponseStatus = serde_json::from_str(body)?;
+    match status.stat {
+        Stat::Ok => serde_json::from_str(body).map_err(FlickrError::from),
+        Stat::Fail => Err(api_error(
+            method,
+            status.code.unwrap_or(0),
+            status.message.unwrap_or_default(),
+        )),
+    }
+}
+
+/// Map Flickr error codes that callers are likely to want to handle onto their own variants
+///
+/// Codes below 95 are specific to each API method, the rest are shared by all methods.
+fn api_error(method: &str, code: u32, message: String) -> FlickrError {
+    match (method, code) {
+        (_, 98) => FlickrError::InvalidToken,
+        (_, 99) => FlickrError::InsufficientPermissions,
+        (_, 100) => FlickrError::InvalidApiKey,
+        (_, 105) => FlickrError::ServiceUnavailable,
+        ("flickr.people.getPhotos", 2)
+        | ("flickr.people.getPublicPhotos", 1)
+        | ("flickr.people.getInfo", 1) => FlickrError::UserNotFound,
+        ("flickr.photos.getExif", 2) => FlickrError::ExifHidden,
+        _ => FlickrError::Api { code, message },
+    }
+}
+
 // For reasons I don't understand the width_k and height_k values are sometimes strings,
 // sometimes numbers.
 #[derive(Debug, Deserialize)]
@@ -71,54 +96,730 @@ impl TryFrom<Dimension> for u32 {
     }
 }
 
+// Coordinates are also a mix of strings and numbers
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Coordinate {
+    Float(f64),
+    String(String),
+}
+
+impl TryFrom<Coordinate> for f64 {
+    type Error = FlickrError;
+
+    fn try_from(coord: Coordinate) -> Result<Self, Self::Error> {
+        match coord {
+            Coordinate::Float(value) => Ok(value),
+            Coordinate::String(value) => value.parse().map_err(FlickrError::from),
+        }
+    }
+}
+
+/// The extras needed to request each of the sizes included in `Photo::sizes`
+pub const SIZE_EXTRAS: [&str; 5] = ["url_c", "url_l", "url_h", "url_k", "url_o"];
+
+/// The extras needed to populate the optional metadata fields of `Photo`
+pub const METADATA_EXTRAS: [&str; 8] = [
+    "date_taken",
+    "date_upload",
+    "owner_name",
+    "description",
+    "tags",
+    "geo",
+    "license",
+    "views",
+];
+
 #[derive(Debug, Deserialize)]
 struct PhotoRaw {
+    id: String,
     title: String,
     #[serde(rename = "ispublic")]
     public: u32,
-    url_k: String, // TODO: Add serde Url crate
-    height_k: Dimension,
-    width_k: Dimension,
+    // Not every photo is available in every size, small or old photos often lack url_k
+    url_c: Option<String>, // TODO: Add serde Url crate
+    height_c: Option<Dimension>,
+    width_c: Option<Dimension>,
+    url_l: Option<String>,
+    height_l: Option<Dimension>,
+    width_l: Option<Dimension>,
+    url_h: Option<String>,
+    height_h: Option<Dimension>,
+    width_h: Option<Dimension>,
+    url_k: Option<String>,
+    height_k: Option<Dimension>,
+    width_k: Option<Dimension>,
+    url_o: Option<String>,
+    height_o: Option<Dimension>,
+    width_o: Option<Dimension>,
     secret: Option<String>,
+    owner: Option<String>,
+    // Included with the owner_name extra, or by default for group pools
+    ownername: Option<String>,
+    // flickr.photos.getContactsPhotos uses username instead of ownername
+    username: Option<String>,
+    // "2018-08-11 13:30:00" in the time zone of the camera
+    datetaken: Option<String>,
+    datetakenunknown: Option<Dimension>,
+    dateupload: Option<Dimension>,
+    description: Option<Element>,
+    // Space separated
+    tags: Option<String>,
+    // 0 when the photo is not geotagged
+    latitude: Option<Coordinate>,
+    longitude: Option<Coordinate>,
+    license: Option<Dimension>,
+    views: Option<Dimension>,
+}
+
+/// Short name of a Flickr license id, as returned by `flickr.photos.licenses.getInfo`
+pub fn license_name(license: u32) -> Option<&'static str> {
+    let name = match license {
+        0 => "All rights reserved",
+        1 => "CC BY-NC-SA 2.0",
+        2 => "CC BY-NC 2.0",
+        3 => "CC BY-NC-ND 2.0",
+        4 => "CC BY 2.0",
+        5 => "CC BY-SA 2.0",
+        6 => "CC BY-ND 2.0",
+        7 => "No known copyright restrictions",
+        8 => "United States Government Work",
+        9 => "CC0 1.0",
+        10 => "Public Domain Mark 1.0",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// One of the sizes a photo is available in
+#[derive(Debug, Clone)]
+pub struct PhotoSize {
+    /// The suffix Flickr uses for this size, e.g. "k" for 2048px
+    pub suffix: &'static str,
+    pub url: Url,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PhotoSize {
+    fn from_raw(
+        suffix: &'static str,
+        url: Option<String>,
+        width: Option<Dimension>,
+        height: Option<Dimension>,
+    ) -> FlickrResult<Option<Self>> {
+        match (url, width, height) {
+            (Some(url), Some(width), Some(height)) => Ok(Some(PhotoSize {
+                suffix,
+                url: url.parse()?,
+                width: u32::try_from(width)?,
+                height: u32::try_from(height)?,
+            })),
+            _ => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Photo {
+    pub id: String,
     pub title: String,
     pub public: bool,
-    pub url_k: Url,
-    pub height_k: u32,
-    pub width_k: u32,
+    /// The sizes returned by Flickr, smallest first
+    pub sizes: Vec<PhotoSize>,
     pub secret: Option<String>,
+    /// NSID of the owner, not included by all methods
+    pub owner: Option<String>,
+    pub owner_name: Option<String>,
+    /// When the photo was taken, in the time zone of the camera
+    pub date_taken: Option<NaiveDateTime>,
+    pub date_upload: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Flickr license id, see `flickr.photos.licenses.getInfo`
+    pub license: Option<u32>,
+    pub views: Option<u32>,
 }
 
 impl TryFrom<PhotoRaw> for Photo {
     type Error = FlickrError;
 
     fn try_from(raw: PhotoRaw) -> Result<Self, Self::Error> {
+        let sizes = vec![
+            PhotoSize::from_raw("c", raw.url_c, raw.width_c, raw.height_c)?,
+            PhotoSize::from_raw("l", raw.url_l, raw.width_l, raw.height_l)?,
+            PhotoSize::from_raw("h", raw.url_h, raw.width_h, raw.height_h)?,
+            PhotoSize::from_raw("k", raw.url_k, raw.width_k, raw.height_k)?,
+            PhotoSize::from_raw("o", raw.url_o, raw.width_o, raw.height_o)?,
+        ];
+        let mut sizes = sizes.into_iter().filter_map(|size| size).collect::<Vec<_>>();
+        sizes.sort_by_key(|size| size.width.max(size.height));
+
+        let date_taken_unknown = match raw.datetakenunknown {
+            Some(unknown) => u32::try_from(unknown)? == 1,
+            None => false,
+        };
+        // Some photos have dates like "0000-00-00 00:00:00", which shouldn't fail the page
+        let date_taken = match raw.datetaken {
+            Some(ref date) if !date_taken_unknown => {
+                NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").ok()
+            }
+            _ => None,
+        };
+        let date_upload = match raw.dateupload {
+            Some(date) => Some(Utc.timestamp(i64::from(u32::try_from(date)?), 0)),
+            None => None,
+        };
+        let (latitude, longitude) = match (raw.latitude, raw.longitude) {
+            (Some(lat), Some(lon)) => (f64::try_from(lat)?, f64::try_from(lon)?),
+            _ => (0., 0.),
+        };
+        let (latitude, longitude) = if latitude == 0. && longitude == 0. {
+            (None, None)
+        } else {
+            (Some(latitude), Some(longitude))
+        };
+        let license = match raw.license {
+            Some(license) => Some(u32::try_from(license)?),
+            None => None,
+        };
+        let views = match raw.views {
+            Some(views) => Some(u32::try_from(views)?),
+            None => None,
+        };
+
         Ok(Photo {
+            id: raw.id,
             title: raw.title,
             public: raw.public == 1,
-            url_k: raw.url_k.parse()?,
-            height_k: u32::try_from(raw.height_k)?,
-            width_k: u32::try_from(raw.width_k)?,
+            sizes,
             secret: raw.secret,
+            owner: raw.owner,
+            owner_name: raw.ownername.or(raw.username),
+            date_taken,
+            date_upload,
+            description: raw.description
+                .map(|description| description.content)
+                .filter(|description| !description.is_empty()),
+            tags: raw.tags
+                .map(|tags| tags.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+            latitude,
+            longitude,
+            license,
+            views,
         })
     }
 }
 
+// Like width_k and height_k the paging values are a mix of strings and numbers depending on the
+// API method.
 #[derive(Debug, Deserialize)]
-pub struct PhotosResponse {
-    photos: PhotosResponsePhotos,
-    stat: Stat,
+pub struct PhotosResponsePhotos {
+    page: Dimension,
+    pages: Dimension,
+    perpage: Dimension,
+    total: Dimension,
+    photo: Vec<PhotoRaw>,
+}
+
+/// A single page of results from a photo listing method
+#[derive(Debug)]
+pub struct PhotosPage {
+    pub page: u32,
+    pub pages: u32,
+    pub per_page: u32,
+    pub total: u32,
+    pub photos: Vec<Photo>,
+}
+
+impl TryFrom<PhotosResponsePhotos> for PhotosPage {
+    type Error = FlickrError;
+
+    fn try_from(raw: PhotosResponsePhotos) -> Result<Self, Self::Error> {
+        Ok(PhotosPage {
+            page: u32::try_from(raw.page)?,
+            pages: u32::try_from(raw.pages)?,
+            per_page: u32::try_from(raw.perpage)?,
+            total: u32::try_from(raw.total)?,
+            photos: raw.photo
+                .into_iter()
+                .map(|photo| Photo::try_from(photo))
+                .collect::<FlickrResult<_>>()?,
+        })
+    }
+}
+
+/// The kinds of content to include in photo listings
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Photos = 1,
+    Screenshots = 2,
+    Other = 3,
+    PhotosAndScreenshots = 4,
+    ScreenshotsAndOther = 5,
+    PhotosAndOther = 6,
+    All = 7,
+}
+
+/// Restricts photo listings to photos with a particular privacy level
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyFilter {
+    Public = 1,
+    Friends = 2,
+    Family = 3,
+    FriendsAndFamily = 4,
+    Private = 5,
+}
+
+/// Optional arguments for `flickr.people.getPhotos`
+///
+/// ```ignore
+/// let request = PhotosRequest::new()
+///     .content_type(ContentType::Photos)
+///     .per_page(100)
+///     .extras(&["url_k"]);
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PhotosRequest {
+    min_upload_date: Option<DateTime<Utc>>,
+    max_upload_date: Option<DateTime<Utc>>,
+    min_taken_date: Option<DateTime<Utc>>,
+    max_taken_date: Option<DateTime<Utc>>,
+    content_type: Option<ContentType>,
+    privacy_filter: Option<PrivacyFilter>,
+    extras: Vec<String>,
+    per_page: Option<u32>,
 }
 
+impl PhotosRequest {
+    pub fn new() -> Self {
+        PhotosRequest::default()
+    }
+
+    pub fn min_upload_date(mut self, date: DateTime<Utc>) -> Self {
+        self.min_upload_date = Some(date);
+        self
+    }
+
+    pub fn max_upload_date(mut self, date: DateTime<Utc>) -> Self {
+        self.max_upload_date = Some(date);
+        self
+    }
+
+    pub fn min_taken_date(mut self, date: DateTime<Utc>) -> Self {
+        self.min_taken_date = Some(date);
+        self
+    }
+
+    pub fn max_taken_date(mut self, date: DateTime<Utc>) -> Self {
+        self.max_taken_date = Some(date);
+        self
+    }
+
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    pub fn privacy_filter(mut self, privacy_filter: PrivacyFilter) -> Self {
+        self.privacy_filter = Some(privacy_filter);
+        self
+    }
+
+    pub fn extras(mut self, extras: &[&str]) -> Self {
+        self.extras
+            .extend(extras.iter().map(|extra| extra.to_string()));
+        self
+    }
+
+    /// Number of photos to return per page. Flickr caps this at 500.
+    pub fn per_page(mut self, per_page: u32) -> Self {
+        self.per_page = Some(per_page);
+        self
+    }
+
+    fn arguments(&self) -> Vec<(&'static str, String)> {
+        let mut arguments = Vec::new();
+
+        // Flickr accepts unix timestamps for both the upload and taken date arguments
+        let dates = [
+            ("min_upload_date", self.min_upload_date),
+            ("max_upload_date", self.max_upload_date),
+            ("min_taken_date", self.min_taken_date),
+            ("max_taken_date", self.max_taken_date),
+        ];
+        for &(name, date) in dates.iter() {
+            if let Some(date) = date {
+                arguments.push((name, date.timestamp().to_string()));
+            }
+        }
+
+        if let Some(content_type) = self.content_type {
+            arguments.push(("content_type", (content_type as u32).to_string()));
+        }
+        if let Some(privacy_filter) = self.privacy_filter {
+            arguments.push(("privacy_filter", (privacy_filter as u32).to_string()));
+        }
+        if !self.extras.is_empty() {
+            arguments.push(("extras", self.extras.join(",")));
+        }
+        if let Some(per_page) = self.per_page {
+            arguments.push(("per_page", per_page.to_string()));
+        }
+
+        arguments
+    }
+}
+
+/// Whether a search must match any or all of the tags
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMode {
+    Any,
+    All,
+}
+
+impl Default for TagMode {
+    fn default() -> Self {
+        TagMode::Any
+    }
+}
+
+/// Search result orderings
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    DatePostedAsc,
+    DatePostedDesc,
+    DateTakenAsc,
+    DateTakenDesc,
+    InterestingnessAsc,
+    InterestingnessDesc,
+    Relevance,
+}
+
+impl Sort {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Sort::DatePostedAsc => "date-posted-asc",
+            Sort::DatePostedDesc => "date-posted-desc",
+            Sort::DateTakenAsc => "date-taken-asc",
+            Sort::DateTakenDesc => "date-taken-desc",
+            Sort::InterestingnessAsc => "interestingness-asc",
+            Sort::InterestingnessDesc => "interestingness-desc",
+            Sort::Relevance => "relevance",
+        }
+    }
+}
+
+/// A geographic area bounded by the given longitudes and latitudes
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RadiusUnits {
+    Km,
+    Mi,
+}
+
+impl Default for RadiusUnits {
+    fn default() -> Self {
+        RadiusUnits::Km
+    }
+}
+
+/// A circular area around a point. Flickr caps the radius at 32km (20mi).
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct Radius {
+    pub lat: f64,
+    pub lon: f64,
+    pub radius: f64,
+    #[serde(default)]
+    pub units: RadiusUnits,
+}
+
+/// Arguments for `flickr.photos.search`
+///
+/// Flickr only returns photos from the last 12 hours for searches without any tags, text, user
+/// or date limits.
+///
+/// ```ignore
+/// let request = SearchRequest::new()
+///     .user_id("me")
+///     .tags(&["beach"], TagMode::Any)
+///     .filter(PhotosRequest::new().min_taken_date(summer_start).max_taken_date(summer_end));
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchRequest {
+    user_id: Option<String>,
+    tags: Vec<String>,
+    tag_mode: TagMode,
+    text: Option<String>,
+    bbox: Option<BoundingBox>,
+    radius: Option<Radius>,
+    sort: Option<Sort>,
+    #[serde(flatten)]
+    filter: PhotosRequest,
+}
+
+impl SearchRequest {
+    pub fn new() -> Self {
+        SearchRequest::default()
+    }
+
+    /// Only return photos owned by `user_id`. "me" is the authenticated user.
+    pub fn user_id(mut self, user_id: &str) -> Self {
+        self.user_id = Some(user_id.to_string());
+        self
+    }
+
+    pub fn tags(mut self, tags: &[&str], mode: TagMode) -> Self {
+        self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self.tag_mode = mode;
+        self
+    }
+
+    /// Free text search of titles, descriptions and tags
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    pub fn bbox(mut self, bbox: BoundingBox) -> Self {
+        self.bbox = Some(bbox);
+        self
+    }
+
+    pub fn radius(mut self, radius: Radius) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// Date, content type, privacy, extras and per page options for the search
+    pub fn filter(mut self, filter: PhotosRequest) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn extras(mut self, extras: &[&str]) -> Self {
+        self.filter = self.filter.extras(extras);
+        self
+    }
+
+    fn arguments(&self) -> Vec<(&'static str, String)> {
+        let mut arguments = self.filter.arguments();
+
+        if let Some(ref user_id) = self.user_id {
+            arguments.push(("user_id", user_id.clone()));
+        }
+        if !self.tags.is_empty() {
+            arguments.push(("tags", self.tags.join(",")));
+            let tag_mode = match self.tag_mode {
+                TagMode::Any => "any",
+                TagMode::All => "all",
+            };
+            arguments.push(("tag_mode", tag_mode.to_string()));
+        }
+        if let Some(ref text) = self.text {
+            arguments.push(("text", text.clone()));
+        }
+        if let Some(bbox) = self.bbox {
+            let bbox = format!(
+                "{},{},{},{}",
+                bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat
+            );
+            arguments.push(("bbox", bbox));
+        }
+        if let Some(radius) = self.radius {
+            let units = match radius.units {
+                RadiusUnits::Km => "km",
+                RadiusUnits::Mi => "mi",
+            };
+            arguments.push(("lat", radius.lat.to_string()));
+            arguments.push(("lon", radius.lon.to_string()));
+            arguments.push(("radius", radius.radius.to_string()));
+            arguments.push(("radius_units", units.to_string()));
+        }
+        if let Some(sort) = self.sort {
+            arguments.push(("sort", sort.as_str().to_string()));
+        }
+
+        arguments
+    }
+
+    /// The arguments that pick which photos are found, e.g. `user_id=me tags=beach`
+    ///
+    /// Extras and the page size are left out as they don't change the results.
+    pub fn describe(&self) -> String {
+        self.arguments()
+            .into_iter()
+            .filter(|&(name, _)| name != "extras" && name != "per_page")
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Lazily requests each page of a photo listing until all pages have been visited
+///
+/// Iteration stops after the first error.
+pub struct PhotoPages<'a> {
+    client: &'a Client,
+    // Listings of public photos aren't signed
+    access_token: Option<&'a AccessToken>,
+    method: &'static str,
+    // Most methods return the list under "photos", photosets use "photoset"
+    key: &'static str,
+    arguments: Vec<(&'static str, String)>,
+    page: u32,
+    pages: Option<u32>,
+    failed: bool,
+}
+
+impl<'a> PhotoPages<'a> {
+    fn new(
+        client: &'a Client,
+        access_token: Option<&'a AccessToken>,
+        method: &'static str,
+        key: &'static str,
+        arguments: Vec<(&'static str, String)>,
+    ) -> Self {
+        PhotoPages {
+            client,
+            access_token,
+            method,
+            key,
+            arguments,
+            page: 1,
+            pages: None,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for PhotoPages<'a> {
+    type Item = FlickrResult<PhotosPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let finished = self.pages.map(|pages| self.page > pages).unwrap_or(false);
+        if self.failed || finished {
+            return None;
+        }
+
+        let mut arguments = self.arguments.clone();
+        arguments.push(("page", self.page.to_string()));
+
+        let key = self.key;
+        let page = self.client
+            .call_with::<serde_json::Map<String, serde_json::Value>>(
+                reqwest::Method::Get,
+                self.method,
+                &arguments,
+                self.access_token,
+            )
+            .and_then(|mut res| {
+                let photos = res.remove(key).unwrap_or(serde_json::Value::Null);
+                serde_json::from_value::<PhotosResponsePhotos>(photos).map_err(FlickrError::from)
+            })
+            .and_then(PhotosPage::try_from);
+
+        match page {
+            Ok(page) => {
+                self.pages = Some(page.pages);
+                self.page += 1;
+                Some(Ok(page))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// flickr.photos.getContactsPhotos is not paginated
 #[derive(Debug, Deserialize)]
-pub struct PhotosResponsePhotos {
+struct ContactsPhotosResponse {
+    photos: ContactsPhotosResponsePhotos,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactsPhotosResponsePhotos {
     photo: Vec<PhotoRaw>,
 }
 
 #[derive(Debug, Deserialize)]
+struct PhotosetRaw {
+    id: String,
+    title: Element,
+    description: Element,
+    photos: Dimension,
+}
+
+/// An album of photos
+#[derive(Debug)]
+pub struct Photoset {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub photos: u32,
+}
+
+impl TryFrom<PhotosetRaw> for Photoset {
+    type Error = FlickrError;
+
+    fn try_from(raw: PhotosetRaw) -> Result<Self, Self::Error> {
+        Ok(Photoset {
+            id: raw.id,
+            title: raw.title.content,
+            description: raw.description.content,
+            photos: u32::try_from(raw.photos)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PhotosetsResponse {
+    photosets: PhotosetsResponsePhotosets,
+}
+
+#[derive(Debug, Deserialize)]
+struct PhotosetsResponsePhotosets {
+    page: Dimension,
+    pages: Dimension,
+    photoset: Vec<PhotosetRaw>,
+}
+
+// {"photoset":{"id":"72157626216528324","url":"https://www.flickr.com/photos/..."},"stat":"ok"}
+#[derive(Debug, Deserialize)]
+struct CreatePhotosetResponse {
+    photoset: CreatePhotosetResponsePhotoset,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatePhotosetResponsePhotoset {
+    id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub nsid: String,
     pub username: String,
@@ -148,6 +849,79 @@ struct Element {
     content: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct ExifResponse {
+    photo: ExifResponsePhoto,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExifResponsePhoto {
+    camera: Option<String>,
+    exif: Vec<ExifTag>,
+}
+
+// {"tagspace":"ExifIFD","tagspaceid":0,"tag":"FNumber","label":"Aperture","raw":{"_content":"4.0"},"clean":{"_content":"f/4.0"}}
+#[derive(Debug, Deserialize)]
+struct ExifTag {
+    tag: String,
+    raw: Element,
+}
+
+/// Camera settings a photo was taken with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Exif {
+    pub camera: Option<String>,
+    pub lens: Option<String>,
+    /// In millimetres
+    pub focal_length: Option<f64>,
+    /// The f-number
+    pub aperture: Option<f64>,
+    /// Shutter speed in seconds, e.g. "1/60"
+    pub exposure_time: Option<String>,
+    pub iso: Option<u32>,
+}
+
+impl From<ExifResponsePhoto> for Exif {
+    fn from(photo: ExifResponsePhoto) -> Self {
+        let mut exif = Exif {
+            camera: photo.camera.filter(|camera| !camera.is_empty()),
+            ..Default::default()
+        };
+        let mut model = None;
+
+        for tag in photo.exif {
+            let value = tag.raw.content;
+            match tag.tag.as_str() {
+                "Model" => model = Some(value),
+                "LensModel" | "Lens" => exif.lens = Some(value),
+                // "24.0 mm"
+                "FocalLength" => exif.focal_length = leading_number(&value),
+                "FNumber" => exif.aperture = leading_number(&value),
+                "ExposureTime" => exif.exposure_time = Some(value),
+                "ISO" => exif.iso = leading_number(&value).map(|iso| iso as u32),
+                _ => (),
+            }
+        }
+
+        // The camera name is derived from the make and model, fall back to the model if Flickr
+        // did not recognise the camera
+        if exif.camera.is_none() {
+            exif.camera = model;
+        }
+
+        exif
+    }
+}
+
+fn leading_number(value: &str) -> Option<f64> {
+    let number = value
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>();
+    number.parse().ok()
+}
+
 #[derive(Debug, Deserialize)]
 struct CheckTokenResponseOauth {
     token: Element,
@@ -155,45 +929,340 @@ struct CheckTokenResponseOauth {
     user: User,
 }
 
-// {"oauth":{"token":{"_content":"72157698177686331-fc8f8f2c03d4fb0d"},"perms":{"_content":"read"},"user":{"nsid":"40215689@N00","username":"wezm","fullname":"Wesley Moore"}},"stat":"ok"}
-#[derive(Debug, Deserialize)]
-struct CheckTokenResponse {
-    oauth: CheckTokenResponseOauth,
-    stat: Stat,
+// {"oauth":{"token":{"_content":"72157698177686331-fc8f8f2c03d4fb0d"},"perms":{"_content":"read"},"user":{"nsid":"40215689@N00","username":"wezm","fullname":"Wesley Moore"}},"stat":"ok"}
+#[derive(Debug, Deserialize)]
+struct CheckTokenResponse {
+    oauth: CheckTokenResponseOauth,
+    stat: Stat,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumerKey(pub String);
+#[derive(Debug, Clone)]
+pub struct ConsumerSecret(pub String);
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenSecret(String);
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessToken {
+    token: String,
+    secret: TokenSecret,
+    /// The user that authorized the token, missing from tokens saved by older versions
+    #[serde(default)]
+    user: Option<User>,
+}
+
+impl AccessToken {
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+}
+pub struct RequestToken {
+    token: String,
+    secret: TokenSecret,
+}
+
+impl Default for TokenSecret {
+    fn default() -> Self {
+        TokenSecret(String::from(""))
+    }
+}
+
+/// The parameters Flickr adds to the callback URL once the user has authorized the app
+#[derive(Debug, PartialEq)]
+pub struct Verifier {
+    pub token: String,
+    pub verifier: String,
+}
+
+/// A temporary HTTP server that receives the OAuth callback
+///
+/// Flickr redirects the browser that authorized the app to the callback URL, so the address
+/// needs to be reachable from that browser, which may be on a phone rather than the frame.
+pub struct CallbackListener {
+    listener: TcpListener,
+    url: Url,
+}
+
+impl CallbackListener {
+    /// Listen on `address`, e.g. "0.0.0.0:8910"
+    ///
+    /// `host` is the host name or IP address browsers should use in the callback URL. It
+    /// defaults to the address being listened on, or the LAN address of the machine when
+    /// listening on all interfaces.
+    pub fn bind(address: &str, host: Option<&str>) -> FlickrResult<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        let local_addr = listener.local_addr()?;
+        let url = match host {
+            Some(host) => format!("http://{}:{}/", host, local_addr.port()),
+            None if local_addr.ip().is_unspecified() => {
+                let ip = lan_address().unwrap_or_else(|_| local_addr.ip());
+                format!("http://{}/", SocketAddr::new(ip, local_addr.port()))
+            }
+            None => format!("http://{}/", local_addr),
+        };
+
+        Ok(CallbackListener {
+            listener,
+            url: Url::parse(&url)?,
+        })
+    }
+
+    /// The URL to pass as the `oauth_callback`
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Wait up to `timeout` for the callback
+    pub fn wait(&self, timeout: Duration) -> FlickrResult<Verifier> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(verifier) = self.poll()? {
+                return Ok(verifier);
+            }
+            if Instant::now() >= deadline {
+                return Err(FlickrError::AuthorizationTimeout(timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Handle a pending request without blocking, returning the verifier if it was the callback
+    ///
+    /// Other requests, such as for favicon.ico, get a 404. Connections that fail, or are left
+    /// idle, are dropped so they can't hold up the callback.
+    pub fn poll(&self) -> FlickrResult<Option<Verifier>> {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) => {
+                println!("callback: unable to accept a connection: {}", err);
+                return Ok(None);
+            }
+        };
+
+        match respond(stream) {
+            Ok(verifier) => Ok(verifier),
+            Err(err) => {
+                println!("callback: dropped a connection: {}", err);
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Read the request on `stream` and answer it, returning the verifier if it was the callback
+fn respond(stream: TcpStream) -> io::Result<Option<Verifier>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    // GET /?oauth_token=72157626737672178-022bbd2f4c2f3432&oauth_verifier=5d1b96a26b494074 HTTP/1.1
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let verifier = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| Url::parse(&format!("http://localhost{}", target)).ok())
+        .and_then(|url| {
+            let mut token = None;
+            let mut verifier = None;
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "oauth_token" => token = Some(value.into_owned()),
+                    "oauth_verifier" => verifier = Some(value.into_owned()),
+                    _ => (),
+                }
+            }
+            match (token, verifier) {
+                (Some(token), Some(verifier)) => Some(Verifier { token, verifier }),
+                _ => None,
+            }
+        });
+
+    let response = match verifier {
+        Some(_) => "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n\
+                    Wallflower has been authorized, you can close this page.\n",
+        None => "HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n",
+    };
+    (&stream).write_all(response.as_bytes())?;
+
+    Ok(verifier)
+}
+
+/// The access an app asks for when it is authorized
+///
+/// Each level includes the ones before it.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+}
+
+impl Permission {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Delete => "delete",
+        }
+    }
+}
+
+impl Default for Permission {
+    fn default() -> Self {
+        Permission::Read
+    }
+}
+
+/// How API calls that fail with a transient error are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Number of retries after the initial attempt
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each subsequent retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter so that several frames don't retry in lock step
+    fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay.as_secs() as f64
+            + f64::from(self.base_delay.subsec_nanos()) / 1e9;
+        let max = self.max_delay.as_secs() as f64;
+        let delay = (base * 2f64.powi(attempt as i32)).min(max);
+        let delay = delay / 2. + rand::thread_rng().gen_range(0., delay / 2. + ::std::f64::EPSILON);
+
+        Duration::from_millis((delay * 1000.) as u64)
+    }
+
+    /// The delay before retrying after `err`, the server's Retry-After if it sent one, but no
+    /// more than `max_delay`
+    fn delay_after(&self, err: &FlickrError, attempt: u32) -> Duration {
+        match *err {
+            FlickrError::HttpStatus {
+                retry_after: Some(retry_after),
+                ..
+            } => retry_after.min(self.max_delay),
+            _ => self.delay(attempt),
+        }
+    }
+
+    /// Only calls that don't change anything are retried, a write may have been applied even
+    /// though the response was lost
+    fn is_retryable(verb: &reqwest::Method, err: &FlickrError) -> bool {
+        *verb == reqwest::Method::Get && RetryPolicy::is_transient(err)
+    }
+
+    fn is_transient(err: &FlickrError) -> bool {
+        match *err {
+            // Timeouts and connection errors
+            FlickrError::HttpError(_) => true,
+            FlickrError::HttpStatus { status, .. } => status == 429 || status >= 500,
+            FlickrError::ServiceUnavailable => true,
+            _ => false,
+        }
+    }
+}
+
+/// Limits the number of API calls made in any hour
+///
+/// Flickr allows about 3600 calls per hour per API key. The budget is shared by every client
+/// it's given to.
+#[derive(Debug)]
+pub struct CallBudget {
+    calls_per_hour: usize,
+    calls: Mutex<VecDeque<Instant>>,
+}
+
+impl CallBudget {
+    pub fn new(calls_per_hour: u32) -> Arc<Self> {
+        Arc::new(CallBudget {
+            calls_per_hour: calls_per_hour as usize,
+            calls: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Record a call, failing if the budget for the last hour has been used up
+    fn spend(&self) -> FlickrResult<()> {
+        let hour = Duration::from_secs(60 * 60);
+        let now = Instant::now();
+        let mut calls = self.calls.lock().unwrap();
+
+        while calls.front().map(|call| now.duration_since(*call) >= hour).unwrap_or(false) {
+            calls.pop_front();
+        }
+
+        if calls.len() >= self.calls_per_hour {
+            // The oldest call is within the last hour so the budget frees up when it expires
+            let retry_after = hour - now.duration_since(calls[0]);
+            return Err(FlickrError::BudgetExhausted { retry_after });
+        }
+
+        calls.push_back(now);
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
-pub struct ConsumerKey(pub String);
-#[derive(Debug)]
-pub struct ConsumerSecret(pub String);
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TokenSecret(String);
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AccessToken {
-    token: String,
-    secret: TokenSecret,
+impl Default for CallBudget {
+    fn default() -> Self {
+        CallBudget {
+            calls_per_hour: 3600,
+            calls: Mutex::new(VecDeque::new()),
+        }
+    }
 }
-pub struct RequestToken {
-    token: String,
-    secret: TokenSecret,
+
+/// The URLs of the REST and OAuth endpoints
+///
+/// Only needs changing to point the clients at a mock server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub rest: String,
+    pub request_token: String,
+    pub authorize: String,
+    pub access_token: String,
 }
 
-impl Default for TokenSecret {
+impl Default for Endpoints {
     fn default() -> Self {
-        TokenSecret(String::from(""))
+        Endpoints {
+            rest: String::from("https://api.flickr.com/services/rest"),
+            request_token: String::from("https://www.flickr.com/services/oauth/request_token"),
+            authorize: String::from("https://www.flickr.com/services/oauth/authorize"),
+            access_token: String::from("https://www.flickr.com/services/oauth/access_token"),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Client {
     consumer_key: ConsumerKey,
     consumer_secret: ConsumerSecret,
+    retry_policy: RetryPolicy,
+    budget: Arc<CallBudget>,
+    transport: Arc<Transport>,
+    endpoints: Endpoints,
+    permission: Permission,
 }
 
 #[derive(Debug)]
 pub struct AuthenticatedClient {
-    consumer_key: ConsumerKey,
-    consumer_secret: ConsumerSecret,
+    client: Client,
     access_token: AccessToken,
 }
 
@@ -202,27 +1271,56 @@ impl Client {
         Client {
             consumer_key: ConsumerKey(consumer_key.to_string()),
             consumer_secret: ConsumerSecret(consumer_secret.to_string()),
+            retry_policy: RetryPolicy::default(),
+            budget: Arc::new(CallBudget::default()),
+            transport: Arc::new(reqwest::Client::new()),
+            endpoints: Endpoints::default(),
+            permission: Permission::default(),
         }
     }
 
+    /// Send requests with `http` instead of a client with the default settings
+    pub fn http_client(self, http: reqwest::Client) -> Self {
+        self.transport(Arc::new(http))
+    }
+
+    /// Send requests through `transport` rather than directly over HTTP
+    pub fn transport(mut self, transport: Arc<Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// The permission to ask for when authorizing, read by default
+    pub fn permission(mut self, permission: Permission) -> Self {
+        self.permission = permission;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Share `budget` with other clients, API calls fail once it has been used up
+    pub fn call_budget(mut self, budget: Arc<CallBudget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
     /// Perform an OAuth 1.0 authentication flow to obtain an access token
+    ///
+    /// The user visits the authorization URL and types the code Flickr shows them into stdin.
     pub fn authenticate(self) -> FlickrResult<AuthenticatedClient> {
-        let request_token = self.get_request_token()?;
-        {
-            let authorization_params = [
-                ("oauth_token", request_token.token.as_str()),
-                ("perms", "read"),
-            ];
-            let authorization_url = Url::parse_with_params(
-                "https://www.flickr.com/services/oauth/authorize",
-                &authorization_params,
-            ).expect("unable to parse authorization_url");
-
-            println!(
-                "Visit this url in your browser to authorize the application:\n\n{}",
-                authorization_url
-            );
-        }
+        let request_token = self.get_request_token("oob")?;
+        println!(
+            "Visit this url in your browser to authorize the application:\n\n{}",
+            self.authorization_url(&request_token)?
+        );
 
         let mut verification_code = String::new();
         while verification_code.trim().is_empty() {
@@ -235,45 +1333,64 @@ impl Client {
         // Exchange request token for access token
         let access_token = self.exchange_request_token(request_token, verification_code.trim())?;
 
-        Ok(AuthenticatedClient {
-            consumer_key: self.consumer_key,
-            consumer_secret: self.consumer_secret,
-            access_token,
-        })
+        Ok(AuthenticatedClient::new(self, access_token))
     }
 
-    fn get_request_token(&self) -> FlickrResult<RequestToken> {
-        let ConsumerKey(ref consumer_key) = self.consumer_key;
+    /// Perform an OAuth 1.0 authorization flow, receiving the verifier through `listener`
+    ///
+    /// `show_url` is given the URL the user needs to visit to authorize the app. Fails with
+    /// `FlickrError::AuthorizationTimeout` if the user does not authorize the app within
+    /// `timeout`.
+    pub fn authorize_with_callback<F: FnOnce(&Url)>(
+        &self,
+        listener: &CallbackListener,
+        timeout: Duration,
+        show_url: F,
+    ) -> FlickrResult<AccessToken> {
+        let request_token = self.get_request_token(listener.url().as_str())?;
+        show_url(&self.authorization_url(&request_token)?);
 
-        let mut params = vec![
-            ("oauth_nonce", generate_nonce()),
-            ("oauth_timestamp", timestamp().to_string()),
-            ("oauth_consumer_key", consumer_key.to_string()),
-            ("oauth_signature_method", String::from("HMAC-SHA1")),
-            ("oauth_version", String::from("1.0")),
-            ("oauth_callback", String::from("oob")),
-        ];
+        let verifier = listener.wait(timeout)?;
+        if verifier.token != request_token.token {
+            return Err(FlickrError::AuthenticationError);
+        }
+        self.exchange_request_token(request_token, &verifier.verifier)
+    }
 
-        let url = "https://www.flickr.com/services/oauth/request_token";
-        let oauth_signature = signature(
-            reqwest::Method::Get,
-            url,
-            &params,
-            &self.consumer_secret,
-            &TokenSecret::default(),
-        );
-        params.push(("oauth_signature", oauth_signature));
+    /// The URL the user visits to authorize `request_token`
+    pub fn authorization_url(&self, request_token: &RequestToken) -> FlickrResult<Url> {
+        let authorization_params = [
+            ("oauth_token", request_token.token.as_str()),
+            ("perms", self.permission.as_str()),
+        ];
+        Ok(Url::parse_with_params(&self.endpoints.authorize, &authorization_params)?)
+    }
 
-        let url = Url::parse_with_params(url, params).expect("Unable to parse url");
+    fn signer(&self) -> Signer {
+        Signer::new(&self.consumer_key.0, &self.consumer_secret.0)
+    }
 
-        let mut res = reqwest::get(url).expect("error requesting request token");
-        let body = res.text().unwrap();
-        println!("{}", body);
+    /// Obtain a request token, the first step of the OAuth flow
+    ///
+    /// `callback` is the URL Flickr redirects to once the user has authorized the token, or
+    /// "oob" to show the user a code instead.
+    pub fn get_request_token(&self, callback: &str) -> FlickrResult<RequestToken> {
+        let url = Url::parse(&self.endpoints.request_token)?;
+        let params = self.signer()
+            .callback(callback)
+            .sign_params(&reqwest::Method::Get, &url, &[]);
+        let url = Url::parse_with_params(url.as_str(), params)?;
+
+        let res = self.transport.send(Request::get(url))?;
+        if !res.is_success() {
+            return Err(FlickrError::AuthenticationError);
+        }
+        let body = res.text();
 
         // oauth_callback_confirmed=true&oauth_token=xxxxxx&oauth_token_secret=xxxxxx
         let mut token = None;
         let mut secret = None;
-        for (key, value) in parse_oauth_response(&body) {
+        for (key, value) in parse_oauth_response(&body)? {
             match key.as_str() {
                 "oauth_token" => token = Some(value),
                 "oauth_token_secret" => secret = Some(value),
@@ -290,38 +1407,25 @@ impl Client {
         }
     }
 
-    fn exchange_request_token(
+    /// Exchange an authorized request token and the verifier Flickr gave the user for an
+    /// access token
+    pub fn exchange_request_token(
         &self,
         request_token: RequestToken,
         verification_code: &str,
     ) -> FlickrResult<AccessToken> {
-        let ConsumerKey(ref consumer_key) = self.consumer_key;
-
-        let mut params = vec![
-            ("oauth_nonce", generate_nonce()),
-            ("oauth_timestamp", timestamp().to_string()),
-            ("oauth_token", request_token.token),
-            ("oauth_verifier", verification_code.to_string()),
-            ("oauth_consumer_key", consumer_key.to_string()),
-            ("oauth_signature_method", String::from("HMAC-SHA1")),
-            ("oauth_version", String::from("1.0")),
-        ];
-
-        let url = "https://www.flickr.com/services/oauth/access_token";
-        let oauth_signature = signature(
-            reqwest::Method::Get,
-            url,
-            &params,
-            &self.consumer_secret,
-            &request_token.secret,
-        );
-        params.push(("oauth_signature", oauth_signature));
-
-        let url = Url::parse_with_params(url, params).expect("Unable to parse url");
-
-        let mut res = reqwest::get(url).expect("error requesting request token");
-        let body = res.text().unwrap();
-        println!("{}", body);
+        let url = Url::parse(&self.endpoints.access_token)?;
+        let params = self.signer()
+            .token(&request_token.token, &request_token.secret.0)
+            .verifier(verification_code)
+            .sign_params(&reqwest::Method::Get, &url, &[]);
+        let url = Url::parse_with_params(url.as_str(), params)?;
+
+        let res = self.transport.send(Request::get(url))?;
+        if !res.is_success() {
+            return Err(FlickrError::AuthenticationError);
+        }
+        let body = res.text();
 
         // Flickr returns a response similar to the following:
         // fullname=Jamal%20Fanaian
@@ -331,10 +1435,16 @@ impl Client {
         // &username=jamalfanaian
         let mut token = None;
         let mut secret = None;
-        for (key, value) in parse_oauth_response(&body) {
+        let mut nsid = None;
+        let mut username = String::new();
+        let mut fullname = String::new();
+        for (key, value) in parse_oauth_response(&body)? {
             match key.as_str() {
                 "oauth_token" => token = Some(value),
                 "oauth_token_secret" => secret = Some(value),
+                "user_nsid" => nsid = Some(value),
+                "username" => username = value,
+                "fullname" => fullname = value,
                 _ => (),
             }
         }
@@ -343,65 +1453,221 @@ impl Client {
             (Some(token), Some(secret)) => Ok(AccessToken {
                 token,
                 secret: TokenSecret(secret),
+                user: nsid.map(|nsid| User {
+                    nsid,
+                    username,
+                    fullname,
+                }),
             }),
             _ => Err(FlickrError::AuthenticationError),
         }
     }
-}
 
-impl AuthenticatedClient {
-    pub fn new(client: Client, access_token: AccessToken) -> Self {
-        AuthenticatedClient {
-            consumer_key: client.consumer_key,
-            consumer_secret: client.consumer_secret,
-            access_token,
+    /// Call an API method that doesn't need authentication, identified only by the API key
+    ///
+    /// Only public photos and details are returned. Failures are retried and calls count
+    /// towards the budget the same as `AuthenticatedClient::call`.
+    pub fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        arguments: &[(&str, String)],
+    ) -> FlickrResult<T> {
+        self.call_with(reqwest::Method::Get, method, arguments, None)
+    }
+
+    /// Fetch the camera settings of a public photo
+    pub fn exif(&self, photo_id: &str) -> FlickrResult<Exif> {
+        let res: ExifResponse =
+            self.call("flickr.photos.getExif", &[("photo_id", photo_id.to_string())])?;
+        Ok(res.photo.into())
+    }
+
+    /// Iterate over the pages of public photos belonging to `user_id`
+    pub fn public_photos(&self, user_id: &str, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
+        arguments.push(("user_id", user_id.to_string()));
+
+        PhotoPages::new(self, None, "flickr.people.getPublicPhotos", "photos", arguments)
+    }
+
+    /// Iterate over the pages of photos in the public photoset `photoset_id`
+    ///
+    /// Only the extras and per page options of `request` are supported by photosets.
+    pub fn photoset_photos(&self, photoset_id: &str, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
+        arguments.push(("photoset_id", photoset_id.to_string()));
+
+        PhotoPages::new(self, None, "flickr.photosets.getPhotos", "photoset", arguments)
+    }
+
+    /// Iterate over the pages of the photos picked for Explore on `date`, or the most recent day
+    /// if not given
+    ///
+    /// Flickr lists at most 500 photos a day. Only the extras and per page options of `request`
+    /// are supported.
+    pub fn interestingness(&self, date: Option<NaiveDate>, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
+        if let Some(date) = date {
+            arguments.push(("date", date.format("%Y-%m-%d").to_string()));
         }
+
+        PhotoPages::new(self, None, "flickr.interestingness.getList", "photos", arguments)
     }
 
-    pub fn access_token(&self) -> &AccessToken {
-        &self.access_token
+    fn call_with<T: DeserializeOwned>(
+        &self,
+        verb: reqwest::Method,
+        method: &str,
+        arguments: &[(&str, String)],
+        access_token: Option<&AccessToken>,
+    ) -> FlickrResult<T> {
+        let mut attempt = 0;
+        loop {
+            self.budget.spend()?;
+
+            match self.call_once(verb.clone(), method, arguments, access_token) {
+                Err(ref err)
+                    if attempt < self.retry_policy.max_retries
+                        && RetryPolicy::is_retryable(&verb, err) =>
+                {
+                    let delay = self.retry_policy.delay_after(err, attempt);
+                    println!("{}: {}, retrying in {:?}", method, err, delay);
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
-    pub fn call<T: DeserializeOwned>(
+    fn call_once<T: DeserializeOwned>(
         &self,
+        verb: reqwest::Method,
         method: &str,
         arguments: &[(&str, String)],
+        access_token: Option<&AccessToken>,
     ) -> FlickrResult<T> {
+        // Authenticated requests are signed for each attempt as OAuth nonces can't be reused
         let mut params = vec![
             ("api_key", self.consumer_key.0.clone()),
-            ("oauth_nonce", generate_nonce()),
-            ("oauth_timestamp", timestamp().to_string()),
-            ("oauth_token", self.access_token.token.clone()),
-            ("oauth_consumer_key", self.consumer_key.0.clone()),
-            ("oauth_signature_method", String::from("HMAC-SHA1")),
-            ("oauth_version", String::from("1.0")),
             ("format", String::from("json")),
             ("nojsoncallback", String::from("1")),
             ("method", method.to_string()),
         ];
         params.append(&mut arguments.to_vec());
 
-        let url = "https://api.flickr.com/services/rest";
-        let oauth_signature = signature(
-            reqwest::Method::Get,
-            url,
-            &params,
-            &self.consumer_secret,
-            &self.access_token.secret,
-        );
-        params.push(("oauth_signature", oauth_signature));
+        let url = Url::parse(&self.endpoints.rest)?;
+        let params = match access_token {
+            Some(access_token) => self.signer()
+                .token(&access_token.token, &access_token.secret.0)
+                .sign_params(&verb, &url, &params),
+            None => params,
+        };
+
+        let request = match verb {
+            reqwest::Method::Post => {
+                let body = params
+                    .iter()
+                    .map(|&(key, ref value)| {
+                        format!("{}={}", oauth1::escape(key), oauth1::escape(value))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("&");
+                Request {
+                    method: verb,
+                    url,
+                    headers: vec![(
+                        String::from("Content-Type"),
+                        String::from("application/x-www-form-urlencoded"),
+                    )],
+                    body: Some(body.into_bytes()),
+                }
+            }
+            _ => Request {
+                method: verb,
+                ..Request::get(Url::parse_with_params(url.as_str(), params)?)
+            },
+        };
+
+        let res = self.transport.send(request)?;
+        if !res.is_success() {
+            return Err(FlickrError::HttpStatus {
+                status: res.status,
+                retry_after: retry_after(&res),
+            });
+        }
+
+        parse_response(method, &res.text())
+    }
+}
+
+impl AuthenticatedClient {
+    pub fn new(client: Client, access_token: AccessToken) -> Self {
+        AuthenticatedClient {
+            client,
+            access_token,
+        }
+    }
+
+    pub fn access_token(&self) -> &AccessToken {
+        &self.access_token
+    }
+
+    /// The NSID of the authenticated user
+    ///
+    /// Tokens saved by older versions don't include the user so it's looked up.
+    pub fn user_id(&self) -> FlickrResult<String> {
+        match self.access_token.user() {
+            Some(user) => Ok(user.nsid.clone()),
+            None => Ok(self.check_token()?.user.nsid),
+        }
+    }
+
+    /// The client without the access token, for calling methods that only return public photos
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Discard the access token, for example so the app can be authorized again after it was
+    /// revoked
+    pub fn into_client(self) -> Client {
+        self.client
+    }
+
+    /// Call an API method, retrying transient failures according to the client's retry policy
+    ///
+    /// Each attempt counts towards the call budget.
+    pub fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        arguments: &[(&str, String)],
+    ) -> FlickrResult<T> {
+        self.client
+            .call_with(reqwest::Method::Get, method, arguments, Some(&self.access_token))
+    }
 
-        let url = Url::parse_with_params(url, params).expect("Unable to parse url");
+    /// Call an API method that changes something with a POST, sending the arguments in the body
+    ///
+    /// Flickr requires POST for methods that need write or delete permission. These calls aren't
+    /// retried as Flickr may have made the change before the failure, creating an album twice
+    /// for example.
+    pub fn post<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        arguments: &[(&str, String)],
+    ) -> FlickrResult<T> {
+        self.client
+            .call_with(reqwest::Method::Post, method, arguments, Some(&self.access_token))
+    }
 
-        // if method == "flickr.people.getPhotos" {
-        //     let body = reqwest::get(url)?.text()?;
-        //     println!("{}", body);
-        //     Err(FlickrError::AuthenticationError)
-        // }
-        // else {
-        let res: T = reqwest::get(url)?.json()?;
-        Ok(res)
-        // }
+    /// Pages of a listing signed with the access token
+    fn pages(
+        &self,
+        method: &'static str,
+        key: &'static str,
+        arguments: Vec<(&'static str, String)>,
+    ) -> PhotoPages {
+        PhotoPages::new(&self.client, Some(&self.access_token), method, key, arguments)
     }
 
     pub fn check_token(&self) -> FlickrResult<OauthToken> {
@@ -409,109 +1675,556 @@ impl AuthenticatedClient {
         Ok(token_res.into())
     }
 
-    // NOTE: This might be a candidate for a builder to make setting optional arguments nicer
-    // {"stat":"fail","code":1,"message":"Required arguments missing"}
-    pub fn photos(&self, user_id: &str, arguments: &[(&str, String)]) -> FlickrResult<Vec<Photo>> {
-        let mut arguments = arguments.to_vec();
+    /// Fetch the camera settings of a photo
+    ///
+    /// Fails with `FlickrError::ExifHidden` if the owner has hidden the EXIF data of their photos.
+    pub fn exif(&self, photo_id: &str) -> FlickrResult<Exif> {
+        let res: ExifResponse =
+            self.call("flickr.photos.getExif", &[("photo_id", photo_id.to_string())])?;
+        Ok(res.photo.into())
+    }
+
+    /// Iterate over the pages of photos belonging to `user_id`
+    pub fn photos(&self, user_id: &str, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
+        arguments.push(("user_id", user_id.to_string()));
+
+        self.pages("flickr.people.getPhotos", "photos", arguments)
+    }
+
+    /// Fetch all of the photosets (albums) belonging to `user_id`
+    pub fn photosets(&self, user_id: &str) -> FlickrResult<Vec<Photoset>> {
+        let mut photosets = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let arguments = [
+                ("user_id", user_id.to_string()),
+                ("per_page", "500".to_string()),
+                ("page", page.to_string()),
+            ];
+            let res: PhotosetsResponse = self.call("flickr.photosets.getList", &arguments)?;
+            let last_page = u32::try_from(res.photosets.page)? >= u32::try_from(res.photosets.pages)?;
+            for photoset in res.photosets.photoset {
+                photosets.push(Photoset::try_from(photoset)?);
+            }
+
+            if last_page {
+                break;
+            }
+            page += 1;
+        }
+
+        Ok(photosets)
+    }
+
+    /// Iterate over the pages of photos in the photoset `photoset_id` owned by `user_id`
+    ///
+    /// Only the privacy filter, extras and per page options of `request` are supported by
+    /// photosets.
+    pub fn photoset_photos(
+        &self,
+        photoset_id: &str,
+        user_id: &str,
+        request: &PhotosRequest,
+    ) -> PhotoPages {
+        let mut arguments = request.arguments();
+        arguments.push(("photoset_id", photoset_id.to_string()));
+        arguments.push(("user_id", user_id.to_string()));
+
+        self.pages("flickr.photosets.getPhotos", "photoset", arguments)
+    }
+
+    /// Iterate over the pages of photos that `user_id` has marked as a favorite
+    ///
+    /// Only the extras and per page options of `request` are supported by favorites.
+    pub fn favorites(&self, user_id: &str, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
         arguments.push(("user_id", user_id.to_string()));
 
-        let res: PhotosResponse = self.call("flickr.people.getPhotos", &arguments)?;
+        self.pages("flickr.favorites.getList", "photos", arguments)
+    }
+
+    /// Iterate over the pages of photos in the pool of the group `group_id`
+    ///
+    /// Only the extras and per page options of `request` are supported by group pools.
+    pub fn group_photos(&self, group_id: &str, request: &PhotosRequest) -> PhotoPages {
+        let mut arguments = request.arguments();
+        arguments.push(("group_id", group_id.to_string()));
+
+        self.pages("flickr.groups.pools.getPhotos", "photos", arguments)
+    }
+
+    /// Iterate over the pages of photos matching `request`
+    pub fn search(&self, request: &SearchRequest) -> PhotoPages {
+        self.pages("flickr.photos.search", "photos", request.arguments())
+    }
+
+    /// Fetch the most recent photos uploaded by the authenticated user's contacts
+    ///
+    /// Flickr returns at most 50 photos and does not paginate the result. Only the extras
+    /// option of `request` is supported.
+    pub fn contacts_photos(
+        &self,
+        just_friends: bool,
+        include_self: bool,
+        request: &PhotosRequest,
+    ) -> FlickrResult<Vec<Photo>> {
+        let mut arguments = request.arguments();
+        arguments.push(("count", "50".to_string()));
+        if just_friends {
+            arguments.push(("just_friends", "1".to_string()));
+        }
+        if include_self {
+            arguments.push(("include_self", "1".to_string()));
+        }
+
+        let res: ContactsPhotosResponse = self.call("flickr.photos.getContactsPhotos", &arguments)?;
         res.photos
             .photo
             .into_iter()
             .map(|photo| Photo::try_from(photo))
             .collect()
     }
+
+    /// Mark a photo as a favorite of the authenticated user. Needs write permission.
+    pub fn add_favorite(&self, photo_id: &str) -> FlickrResult<()> {
+        let arguments = [("photo_id", photo_id.to_string())];
+        self.post::<serde_json::Value>("flickr.favorites.add", &arguments)?;
+        Ok(())
+    }
+
+    /// Create a photoset containing `primary_photo_id`, returning its id. Needs write permission.
+    pub fn create_photoset(&self, title: &str, primary_photo_id: &str) -> FlickrResult<String> {
+        let arguments = [
+            ("title", title.to_string()),
+            ("primary_photo_id", primary_photo_id.to_string()),
+        ];
+        let res: CreatePhotosetResponse = self.post("flickr.photosets.create", &arguments)?;
+        Ok(res.photoset.id)
+    }
+
+    /// Add a photo to a photoset. Needs write permission.
+    pub fn add_to_photoset(&self, photoset_id: &str, photo_id: &str) -> FlickrResult<()> {
+        let arguments = [
+            ("photoset_id", photoset_id.to_string()),
+            ("photo_id", photo_id.to_string()),
+        ];
+        self.post::<serde_json::Value>("flickr.photosets.addPhoto", &arguments)?;
+        Ok(())
+    }
+}
+
+/// The address of the interface used to reach the internet
+fn lan_address() -> io::Result<IpAddr> {
+    // Connecting a UDP socket doesn't send anything, it just chooses the route
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("8.8.8.8:80")?;
+    Ok(socket.local_addr()?.ip())
 }
 
-fn generate_nonce() -> String {
-    Uuid::new_v4().to_string()
+/// Parse the Retry-After header, which is either a number of seconds or an HTTP date
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.header("Retry-After")?;
+
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    DateTime::<FixedOffset>::parse_from_rfc2822(value.trim())
+        .ok()
+        .and_then(|date| (date.with_timezone(&Utc) - Utc::now()).to_std().ok())
 }
 
-fn timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("SystemTime before UNIX_EPOCH")
-        .as_secs()
+/// Parse the form encoded body of an OAuth token response
+fn parse_oauth_response(body: &str) -> FlickrResult<Vec<(String, String)>> {
+    // Form encoding uses '+' for spaces, a literal '+' is sent as %2B
+    let decode = |value: &str| {
+        percent_decode(value.replace('+', " ").as_bytes())
+            .decode_utf8()
+            .map(|value| value.into_owned())
+            .map_err(|_| FlickrError::MalformedOauthResponse(body.to_string()))
+    };
+
+    body.trim()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut iter = pair.splitn(2, '=');
+            match (iter.next(), iter.next()) {
+                (Some(key), Some(value)) => Ok((decode(key)?, decode(value)?)),
+                _ => Err(FlickrError::MalformedOauthResponse(body.to_string())),
+            }
+        })
+        .collect()
 }
 
-fn escape(value: &str) -> String {
-    // Percent encode according to OAuth requirements
-    utf8_percent_encode(value, UNRESERVED_ENCODE_SET).collect::<String>()
+#[test]
+fn test_photos_request_arguments() {
+    let request = PhotosRequest::new()
+        .min_taken_date(Utc.timestamp(1388494800, 0))
+        .content_type(ContentType::Photos)
+        .per_page(100)
+        .extras(&["url_k", "date_taken"]);
+
+    assert_eq!(
+        request.arguments(),
+        vec![
+            ("min_taken_date", String::from("1388494800")),
+            ("content_type", String::from("1")),
+            ("extras", String::from("url_k,date_taken")),
+            ("per_page", String::from("100")),
+        ]
+    );
 }
 
-fn sign(base_string: &str, consumer_secret: &ConsumerSecret, token_secret: &TokenSecret) -> String {
-    let key = format!("{}&{}", escape(&consumer_secret.0), escape(&token_secret.0));
-    let mut mac = HmacSha1::new_varkey(key.as_bytes()).expect("Unable to create HMACer");
-    mac.input(base_string.as_bytes());
+#[test]
+fn test_search_request_arguments() {
+    let request = SearchRequest::new()
+        .user_id("me")
+        .tags(&["beach", "summer"], TagMode::All)
+        .bbox(BoundingBox {
+            min_lon: 144.5,
+            min_lat: -38.5,
+            max_lon: 145.5,
+            max_lat: -37.5,
+        })
+        .sort(Sort::DateTakenDesc)
+        .extras(&["url_k"]);
+
+    assert_eq!(
+        request.arguments(),
+        vec![
+            ("extras", String::from("url_k")),
+            ("user_id", String::from("me")),
+            ("tags", String::from("beach,summer")),
+            ("tag_mode", String::from("all")),
+            ("bbox", String::from("144.5,-38.5,145.5,-37.5")),
+            ("sort", String::from("date-taken-desc")),
+        ]
+    );
+}
 
-    // oauth_signature is set to the calculated digest octet string, first base64-encoded per
-    // [RFC2045] section 6.8, then URL-encoded
-    base64::encode(&mac.result().code())
+#[test]
+fn test_search_request_describe() {
+    let request = SearchRequest::new()
+        .tags(&["beach"], TagMode::Any)
+        .text("sunset")
+        .filter(PhotosRequest::new().per_page(100))
+        .extras(&["url_k"]);
+
+    assert_eq!(request.describe(), "tags=beach tag_mode=any text=sunset");
+    assert_eq!(SearchRequest::new().user_id("me").describe(), "user_id=me");
 }
 
-fn signature_base_string(
-    verb: reqwest::Method,
-    base_url: &str,
-    params: &[(&str, String)],
-) -> String {
-    let mut params = params
+#[test]
+fn test_photo_missing_sizes() {
+    let json = r#"{"id":"1","title":"Old photo","ispublic":1,"url_c":"https://farm1.staticflickr.com/1/1_a_c.jpg","height_c":"600","width_c":800,"url_l":"https://farm1.staticflickr.com/1/1_a_b.jpg","height_l":768,"width_l":"1024"}"#;
+    let raw: PhotoRaw = serde_json::from_str(json).unwrap();
+    let photo = Photo::try_from(raw).unwrap();
+
+    let sizes = photo
+        .sizes
         .iter()
-        .map(|(k, v)| format!("{}={}", escape(k), escape(v)))
+        .map(|size| (size.suffix, size.width, size.height))
+        .collect::<Vec<_>>();
+    assert_eq!(sizes, vec![("c", 800, 600), ("l", 1024, 768)]);
+}
+
+#[test]
+fn test_photo_metadata() {
+    let json = r#"{"id":"1","title":"Beach","ispublic":1,"ownername":"wezm","datetaken":"2018-01-20 17:45:12","datetakengranularity":"0","datetakenunknown":"0","dateupload":"1516433112","description":{"_content":"Sunset at the beach"},"tags":"beach sunset","latitude":"-37.863","longitude":144.972,"accuracy":"16","license":"4","views":"123"}"#;
+    let raw: PhotoRaw = serde_json::from_str(json).unwrap();
+    let photo = Photo::try_from(raw).unwrap();
+
+    assert_eq!(
+        photo.date_taken,
+        Some(NaiveDateTime::parse_from_str("2018-01-20 17:45:12", "%Y-%m-%d %H:%M:%S").unwrap())
+    );
+    assert_eq!(photo.date_upload, Some(Utc.timestamp(1516433112, 0)));
+    assert_eq!(photo.description, Some(String::from("Sunset at the beach")));
+    assert_eq!(photo.tags, vec!["beach", "sunset"]);
+    assert_eq!((photo.latitude, photo.longitude), (Some(-37.863), Some(144.972)));
+    assert_eq!((photo.license, photo.views), (Some(4), Some(123)));
+
+    let json = r#"{"id":"2","title":"Old","ispublic":1,"datetaken":"0000-00-00 00:00:00","datetakenunknown":"0"}"#;
+    let photo = Photo::try_from(serde_json::from_str::<PhotoRaw>(json).unwrap()).unwrap();
+    assert_eq!(photo.date_taken, None);
+}
+
+#[test]
+fn test_exif() {
+    let json = r#"{"camera":"Canon EOS 5D Mark II","exif":[{"tagspace":"IFD0","tagspaceid":0,"tag":"Make","label":"Make","raw":{"_content":"Canon"}},{"tagspace":"ExifIFD","tagspaceid":0,"tag":"ExposureTime","label":"Exposure","raw":{"_content":"1/60"},"clean":{"_content":"0.017 sec (1/60)"}},{"tagspace":"ExifIFD","tagspaceid":0,"tag":"FNumber","label":"Aperture","raw":{"_content":"4.0"},"clean":{"_content":"f/4.0"}},{"tagspace":"ExifIFD","tagspaceid":0,"tag":"ISO","label":"ISO Speed","raw":{"_content":"400"}},{"tagspace":"ExifIFD","tagspaceid":0,"tag":"FocalLength","label":"Focal Length","raw":{"_content":"24.0 mm"},"clean":{"_content":"24 mm"}},{"tagspace":"ExifIFD","tagspaceid":0,"tag":"LensModel","label":"Lens Model","raw":{"_content":"EF24-105mm f/4L IS USM"}}]}"#;
+    let photo: ExifResponsePhoto = serde_json::from_str(json).unwrap();
+    let exif = Exif::from(photo);
+
+    assert_eq!(exif.camera, Some(String::from("Canon EOS 5D Mark II")));
+    assert_eq!(exif.lens, Some(String::from("EF24-105mm f/4L IS USM")));
+    assert_eq!(exif.focal_length, Some(24.));
+    assert_eq!(exif.aperture, Some(4.));
+    assert_eq!(exif.exposure_time, Some(String::from("1/60")));
+    assert_eq!(exif.iso, Some(400));
+}
+
+#[test]
+fn test_call_budget() {
+    let budget = CallBudget::new(2);
+    assert!(budget.spend().is_ok());
+    assert!(budget.spend().is_ok());
+    match budget.spend() {
+        Err(FlickrError::BudgetExhausted { retry_after }) => {
+            assert!(retry_after <= Duration::from_secs(60 * 60))
+        }
+        other => panic!("expected BudgetExhausted, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy {
+        max_retries: 5,
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(4),
+    };
+
+    for attempt in 0..5 {
+        let delay = policy.delay(attempt);
+        let cap = Duration::from_secs(4u64.min(1 << attempt));
+        assert!(delay >= cap / 2 && delay <= cap, "{:?} for attempt {}", delay, attempt);
+    }
+
+    let throttled = FlickrError::HttpStatus {
+        status: 429,
+        retry_after: Some(Duration::from_secs(3600)),
+    };
+    assert_eq!(policy.delay_after(&throttled, 0), Duration::from_secs(4));
+}
+
+#[test]
+fn test_photos_page() {
+    let json = r#"{"page":2,"pages":"89","perpage":10,"total":"881","photo":[]}"#;
+    let raw: PhotosResponsePhotos = serde_json::from_str(json).unwrap();
+    let page = PhotosPage::try_from(raw).unwrap();
+
+    assert_eq!(
+        (page.page, page.pages, page.per_page, page.total),
+        (2, 89, 10, 881)
+    );
+}
+
+#[test]
+fn test_parse_response_ok() {
+    let body = r#"{"oauth":{"token":{"_content":"72157698177686331-fc8f8f2c03d4fb0d"},"perms":{"_content":"read"},"user":{"nsid":"40215689@N00","username":"wezm","fullname":"Wesley Moore"}},"stat":"ok"}"#;
+    let res: CheckTokenResponse = parse_response("flickr.auth.oauth.checkToken", body).unwrap();
+
+    assert_eq!(res.oauth.user.nsid, "40215689@N00");
+}
+
+#[test]
+fn test_parse_response_fail() {
+    let body = r#"{"stat":"fail","code":98,"message":"Invalid auth token"}"#;
+    match parse_response::<CheckTokenResponse>("flickr.auth.oauth.checkToken", body) {
+        Err(FlickrError::InvalidToken) => (),
+        other => panic!("expected InvalidToken, got {:?}", other),
+    }
+
+    let body = r#"{"stat":"fail","code":1,"message":"Required arguments missing"}"#;
+    match parse_response::<serde_json::Value>("flickr.people.getPhotos", body) {
+        Err(FlickrError::Api { code: 1, .. }) => (),
+        other => panic!("expected Api error, got {:?}", other),
+    }
+
+    let body = r#"{"stat":"fail","code":2,"message":"Permission denied"}"#;
+    match parse_response::<serde_json::Value>("flickr.photos.getExif", body) {
+        Err(FlickrError::ExifHidden) => (),
+        other => panic!("expected ExifHidden, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+fn fixture_client(responses: &[(u16, &str)]) -> (Client, Arc<::http::FixtureTransport>) {
+    let transport = Arc::new(::http::FixtureTransport::new(responses));
+    let endpoints = Endpoints {
+        rest: String::from("http://localhost/rest"),
+        request_token: String::from("http://localhost/request_token"),
+        authorize: String::from("http://localhost/authorize"),
+        access_token: String::from("http://localhost/access_token"),
+    };
+    let client = Client::new("key", "secret")
+        .transport(transport.clone())
+        .endpoints(endpoints)
+        .retry_policy(RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        });
+
+    (client, transport)
+}
+
+#[cfg(test)]
+fn fixture_authenticated_client(
+    responses: &[(u16, &str)],
+) -> (AuthenticatedClient, Arc<::http::FixtureTransport>) {
+    let (client, transport) = fixture_client(responses);
+    let access_token = AccessToken {
+        token: String::from("token"),
+        secret: TokenSecret(String::from("token-secret")),
+        user: None,
+    };
+
+    (AuthenticatedClient::new(client, access_token), transport)
+}
+
+#[test]
+fn test_check_token_transport() {
+    let body = r#"{"oauth":{"token":{"_content":"token"},"perms":{"_content":"read"},"user":{"nsid":"40215689@N00","username":"wezm","fullname":"Wesley Moore"}},"stat":"ok"}"#;
+    let (client, transport) = fixture_authenticated_client(&[(503, ""), (200, body)]);
+
+    let token = client.check_token().unwrap();
+    assert_eq!(token.user.nsid, "40215689@N00");
+
+    // The 503 is retried
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].url.path(), "/rest");
+    drop(requests);
+    assert_eq!(transport.param(1, "method"), Some(String::from("flickr.auth.oauth.checkToken")));
+    assert_eq!(transport.param(1, "oauth_token"), Some(String::from("token")));
+    assert!(transport.param(1, "oauth_signature").is_some());
+}
+
+#[test]
+fn test_photos_transport() {
+    let page1 = r#"{"photos":{"page":1,"pages":2,"perpage":1,"total":"2","photo":[{"id":"1","title":"One","ispublic":1}]},"stat":"ok"}"#;
+    let page2 = r#"{"photos":{"page":2,"pages":2,"perpage":1,"total":"2","photo":[{"id":"2","title":"Two","ispublic":1}]},"stat":"ok"}"#;
+    let (client, transport) = fixture_authenticated_client(&[(200, page1), (200, page2)]);
+
+    let ids = client
+        .photos("me", &PhotosRequest::new().per_page(1))
+        .map(|page| page.unwrap().photos.into_iter().map(|photo| photo.id))
+        .flat_map(|ids| ids)
         .collect::<Vec<_>>();
-    params.sort();
 
-    format!(
-        "{}&{}&{}",
-        verb.to_string(),
-        escape(base_url),
-        escape(&params.join("&")),
-    )
+    assert_eq!(ids, vec!["1", "2"]);
+    assert_eq!(transport.param(0, "page"), Some(String::from("1")));
+    assert_eq!(transport.param(1, "page"), Some(String::from("2")));
+    assert_eq!(transport.param(1, "user_id"), Some(String::from("me")));
 }
 
-fn signature(
-    verb: reqwest::Method,
-    base_url: &str,
-    params: &[(&str, String)],
-    consumer_secret: &ConsumerSecret,
-    token_secret: &TokenSecret,
-) -> String {
-    let base_string = signature_base_string(verb, base_url, params);
-    sign(&base_string, consumer_secret, token_secret)
+#[test]
+fn test_public_transport() {
+    let page = r#"{"photos":{"page":1,"pages":1,"perpage":100,"total":1,"photo":[{"id":"1","title":"One","ispublic":1}]},"stat":"ok"}"#;
+    let (client, transport) = fixture_client(&[(200, page)]);
+
+    let date = NaiveDate::from_ymd(2018, 9, 1);
+    let pages = client.interestingness(Some(date), &PhotosRequest::new()).collect::<Vec<_>>();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].as_ref().unwrap().photos[0].id, "1");
+
+    assert_eq!(
+        transport.param(0, "method"),
+        Some(String::from("flickr.interestingness.getList"))
+    );
+    assert_eq!(transport.param(0, "date"), Some(String::from("2018-09-01")));
+    assert!(transport.param(0, "api_key").is_some());
+    // Public calls aren't signed
+    assert_eq!(transport.param(0, "oauth_signature"), None);
+    assert_eq!(transport.param(0, "oauth_token"), None);
 }
 
-fn parse_oauth_response(body: &str) -> Vec<(String, String)> {
-    body.split("&")
-        .map(|pair| {
-            let mut iter = pair.split("=");
-            match (iter.next(), iter.next()) {
-                (Some(key), Some(value)) => (key.to_string(), value.to_string()),
-                _ => panic!("malformed OAuth response"),
-            }
-        })
-        .collect()
+#[test]
+fn test_oauth_transport() {
+    let (client, transport) = fixture_client(&[
+        (200, "oauth_callback_confirmed=true&oauth_token=request&oauth_token_secret=request-secret"),
+        (200, "fullname=Jamal%20Fanaian&oauth_token=access&oauth_token_secret=access-secret&user_nsid=21207597%40N07&username=jamalfanaian"),
+    ]);
+
+    let request_token = client.get_request_token("oob").unwrap();
+    assert_eq!(request_token.token, "request");
+    let access_token = client.exchange_request_token(request_token, "verifier").unwrap();
+    assert_eq!(access_token.token, "access");
+    let user = access_token.user().unwrap();
+    assert_eq!(user.nsid, "21207597@N07");
+    assert_eq!(user.fullname, "Jamal Fanaian");
+
+    assert_eq!(transport.requests.lock().unwrap()[0].url.path(), "/request_token");
+    assert_eq!(transport.param(1, "oauth_token"), Some(String::from("request")));
+    assert_eq!(transport.param(1, "oauth_verifier"), Some(String::from("verifier")));
 }
 
 #[test]
-fn test_signature_base_string() {
-    // Verify example from https://www.flickr.com/services/api/auth.oauth.html
-    let params = [
-        ("oauth_nonce", String::from("95613465")),
-        ("oauth_timestamp", String::from("1305586162")),
-        (
-            "oauth_consumer_key",
-            String::from("653e7a6ecc1d528c516cc8f92cf98611"),
-        ),
-        ("oauth_signature_method", String::from("HMAC-SHA1")),
-        ("oauth_version", String::from("1.0")),
-        ("oauth_callback", String::from("http://www.example.com")),
-    ];
-
-    assert_eq!(signature_base_string(reqwest::Method::Get, "https://www.flickr.com/services/oauth/request_token", &params), "GET&https%3A%2F%2Fwww.flickr.com%2Fservices%2Foauth%2Frequest_token&oauth_callback%3Dhttp%253A%252F%252Fwww.example.com%26oauth_consumer_key%3D653e7a6ecc1d528c516cc8f92cf98611%26oauth_nonce%3D95613465%26oauth_signature_method%3DHMAC-SHA1%26oauth_timestamp%3D1305586162%26oauth_version%3D1.0");
-}
-
-// #[test]
-// fn test_sign() {
-//     // This verifies the example from https://www.flickr.com/services/api/auth.oauth.html
-//     assert_eq!(sign("GET&https%3A%2F%2Fwww.flickr.com%2Fservices%2Foauth%2Frequest_token&oauth_callback%3Dhttp%253A%252F%252Fwww.example.com%26oauth_consumer_key%3D653e7a6ecc1d528c516cc8f92cf98611%26oauth_nonce%3D95613465%26oauth_signature_method%3DHMAC-SHA1%26oauth_timestamp%3D1305586162%26oauth_version%3D1.0"), "7w18YS2bONDPL/zgyzP5XTr5af4=");
-// }
+fn test_callback_listener() {
+    let listener = CallbackListener::bind("127.0.0.1:0", None).unwrap();
+    let address = listener.url().host_str().unwrap().to_string();
+    let port = listener.url().port().unwrap();
+
+    let browser = thread::spawn(move || {
+        // A pre-connect that never sends a request times out without ending the wait
+        let idle = TcpStream::connect((address.as_str(), port)).unwrap();
+        for target in &["/favicon.ico", "/?oauth_token=token&oauth_verifier=5d1b96a26b494074"] {
+            let mut stream = TcpStream::connect((address.as_str(), port)).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+            let mut status = String::new();
+            BufReader::new(stream).read_line(&mut status).unwrap();
+        }
+        drop(idle);
+    });
+
+    let verifier = listener.wait(Duration::from_secs(15)).unwrap();
+    browser.join().unwrap();
+    assert_eq!(
+        verifier,
+        Verifier {
+            token: String::from("token"),
+            verifier: String::from("5d1b96a26b494074"),
+        }
+    );
+
+    match listener.wait(Duration::from_millis(200)) {
+        Err(FlickrError::AuthorizationTimeout(_)) => (),
+        other => panic!("expected AuthorizationTimeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_post_transport() {
+    let (client, transport) = fixture_authenticated_client(&[(200, r#"{"stat":"ok"}"#)]);
+    client.add_favorite("2636").unwrap();
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].method, reqwest::Method::Post);
+    assert_eq!(requests[0].url.query(), None);
+    let body = String::from_utf8(requests[0].body.clone().unwrap()).unwrap();
+    assert!(body.contains("method=flickr.favorites.add"));
+    assert!(body.contains("photo_id=2636"));
+    assert!(body.contains("oauth_signature="));
+}
+
+#[test]
+fn test_post_not_retried() {
+    let (client, transport) =
+        fixture_authenticated_client(&[(503, ""), (200, r#"{"stat":"ok"}"#)]);
+    match client.add_favorite("2636") {
+        Err(FlickrError::HttpStatus { status: 503, .. }) => (),
+        other => panic!("expected HttpStatus 503, got {:?}", other),
+    }
+    assert_eq!(transport.requests.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_parse_oauth_response() {
+    let body = "fullname=Jamal%20Fanaian&oauth_token=72157626318069415-087bfc7b5816092c&oauth_token_secret=a202d1f853ec69de&user_nsid=21207597%40N07&username=jamalfanaian\n";
+    let params = parse_oauth_response(body).unwrap();
+    assert_eq!(params[0], (String::from("fullname"), String::from("Jamal Fanaian")));
+    assert_eq!(params[3], (String::from("user_nsid"), String::from("21207597@N07")));
+
+    let params = parse_oauth_response("fullname=Jamal+Fanaian&username=a%2Bb").unwrap();
+    assert_eq!(params[0], (String::from("fullname"), String::from("Jamal Fanaian")));
+    assert_eq!(params[1], (String::from("username"), String::from("a+b")));
+
+    match parse_oauth_response("oauth_problem=token_rejected&oops") {
+        Err(FlickrError::MalformedOauthResponse(_)) => (),
+        other => panic!("expected MalformedOauthResponse, got {:?}", other),
+    }
+}
@@ -0,0 +1,237 @@
+use reqwest::{self, Certificate, Method, Proxy, Url};
+use reqwest::header::{Headers, UserAgent};
+
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use WallflowerError;
+
+/// A request sent through a `Transport`
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl Request {
+    pub fn get(url: Url) -> Self {
+        Request {
+            method: Method::Get,
+            url,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+}
+
+/// A fully read response from a `Transport`
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// The value of the first header called `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref header, _)| header.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum TransportError {
+    #[fail(display = "HTTP error")] HttpError(reqwest::Error),
+    #[fail(display = "I/O error")] IoError(io::Error),
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(err: reqwest::Error) -> Self {
+        TransportError::HttpError(err)
+    }
+}
+
+impl From<io::Error> for TransportError {
+    fn from(err: io::Error) -> Self {
+        TransportError::IoError(err)
+    }
+}
+
+/// Sends HTTP requests for the API clients
+///
+/// Implemented by `reqwest::Client`, tests use canned responses instead.
+pub trait Transport: Debug + Send + Sync {
+    fn send(&self, request: Request) -> Result<Response, TransportError>;
+}
+
+impl Transport for reqwest::Client {
+    fn send(&self, request: Request) -> Result<Response, TransportError> {
+        let mut headers = Headers::new();
+        for (name, value) in request.headers {
+            headers.set_raw(name, value);
+        }
+
+        let mut builder = self.request(request.method, request.url);
+        builder.headers(headers);
+        if let Some(body) = request.body {
+            builder.body(body);
+        }
+
+        let mut res = builder.send()?;
+        let mut body = Vec::new();
+        res.read_to_end(&mut body)?;
+
+        Ok(Response {
+            status: res.status().as_u16(),
+            headers: res.headers()
+                .iter()
+                .map(|header| (header.name().to_string(), header.value_string()))
+                .collect(),
+            body,
+        })
+    }
+}
+
+/// Settings for the HTTP client shared by the Flickr, weather and photo download code
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Timeout for each request, in seconds
+    pub timeout: u64,
+    pub user_agent: String,
+    /// URL of a proxy to send all requests through
+    pub proxy: Option<String>,
+    /// Directory of PEM encoded certificates to trust in addition to the system ones
+    pub ca_dir: Option<PathBuf>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout: 30,
+            user_agent: format!("Wallflower/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            ca_dir: None,
+        }
+    }
+}
+
+/// Build a client from `config`
+///
+/// The client keeps connections alive between requests. Clones share the same connection pool
+/// so a single client should be created and cloned wherever it's needed.
+pub fn client(config: &HttpConfig) -> Result<reqwest::Client, WallflowerError> {
+    let mut headers = Headers::new();
+    headers.set(UserAgent::new(config.user_agent.clone()));
+
+    let mut builder = reqwest::Client::builder();
+    builder
+        .timeout(Duration::from_secs(config.timeout))
+        .default_headers(headers);
+
+    if let Some(ref proxy) = config.proxy {
+        builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+
+    if let Some(ref ca_dir) = config.ca_dir {
+        for certificate in load_certificates(ca_dir)? {
+            builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Load every certificate in the .pem and .crt files in `dir`
+fn load_certificates(dir: &Path) -> Result<Vec<Certificate>, WallflowerError> {
+    let mut certificates = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_certificate = path.extension()
+            .map(|ext| ext == "pem" || ext == "crt")
+            .unwrap_or(false);
+        if !is_certificate || !path.is_file() {
+            continue;
+        }
+
+        let mut pem = String::new();
+        File::open(&path)?.read_to_string(&mut pem)?;
+
+        // Bundles contain several certificates but each Certificate can only hold one
+        let end = "-----END CERTIFICATE-----";
+        for block in pem.split(end).filter(|block| block.contains("-----BEGIN CERTIFICATE-----")) {
+            let block = format!("{}{}\n", block.trim_left(), end);
+            certificates.push(Certificate::from_pem(block.as_bytes())?);
+        }
+    }
+
+    Ok(certificates)
+}
+
+/// Replays canned responses in order and records the requests it was sent
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct FixtureTransport {
+    responses: ::std::sync::Mutex<Vec<Response>>,
+    pub requests: ::std::sync::Mutex<Vec<Request>>,
+}
+
+#[cfg(test)]
+impl FixtureTransport {
+    pub fn new(responses: &[(u16, &str)]) -> Self {
+        let responses = responses
+            .iter()
+            .rev()
+            .map(|&(status, body)| Response {
+                status,
+                headers: Vec::new(),
+                body: body.as_bytes().to_vec(),
+            })
+            .collect();
+
+        FixtureTransport {
+            responses: ::std::sync::Mutex::new(responses),
+            requests: Default::default(),
+        }
+    }
+
+    /// The value of the query parameter `name` in the `index`th request
+    pub fn param(&self, index: usize, name: &str) -> Option<String> {
+        let requests = self.requests.lock().unwrap();
+        requests[index]
+            .url
+            .query_pairs()
+            .find(|&(ref key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+#[cfg(test)]
+impl Transport for FixtureTransport {
+    fn send(&self, request: Request) -> Result<Response, TransportError> {
+        self.requests.lock().unwrap().push(request);
+        self.responses.lock().unwrap().pop().ok_or_else(|| {
+            TransportError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no more fixture responses",
+            ))
+        })
+    }
+}
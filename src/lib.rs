@@ -5,21 +5,30 @@ extern crate failure_derive;
 extern crate serde_derive;
 extern crate chrono;
 extern crate env_logger;
+extern crate exif;
 extern crate glfw_window;
 extern crate graphics;
 extern crate image;
 extern crate opengl_graphics;
 extern crate percent_encoding;
 extern crate piston;
+extern crate qrcode;
 extern crate reqwest;
 extern crate serde_json;
 extern crate threadpool;
 
+pub mod catalog;
+pub mod config;
 pub mod error;
 pub mod flickr;
+pub mod http;
+pub mod oauth1;
+pub mod playlist;
 pub mod weather;
+pub mod setup;
 pub mod slideshow;
 pub mod statusbar;
+pub mod token;
 
 pub use error::FlickrError;
 pub use error::WallflowerError;
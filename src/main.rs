@@ -11,7 +11,7 @@ extern crate serde_json;
 extern crate threadpool;
 extern crate wallflower;
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, Utc};
 use piston::event_loop::*;
 use piston::input::*;
 use piston::window::{Size, Window, WindowSettings};
@@ -20,16 +20,32 @@ use opengl_graphics::*;
 use glfw_window::GlfwWindow;
 
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
 use std::thread::{self, sleep};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::env;
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 
+use wallflower::catalog::{Catalog, ShownLog};
+use wallflower::playlist::Playlist;
+use wallflower::slideshow::{SyncAccount, SyncEvent};
 use wallflower::weather::{self, Observation};
 use wallflower::flickr;
-use wallflower::{slideshow, WallflowerError, statusbar};
-
-const FLICKR_DATA_FILE: &str = ".flickr-data.json";
+use wallflower::setup::{self, SetupEvent};
+use wallflower::token::{self, TokenStore};
+use wallflower::{config, http, slideshow, WallflowerError, statusbar};
+
+// Where older versions saved the access token
+const LEGACY_TOKEN_FILE: &str = ".flickr-data.json";
+const CONFIG_FILE: &str = "wallflower.json";
+const SETUP_PROMPT: &str = "Scan the code with your phone to let Wallflower show your Flickr photos";
+const REVOKED_PROMPT: &str = "Flickr access was revoked, scan the code to authorize Wallflower again";
+const SYNCING_MESSAGE: &str = "Syncing photos from Flickr...";
+const NO_PHOTOS_MESSAGE: &str = "No photos to show yet";
+// How often to save when photos were shown, in seconds, to limit writes to the SD card
+const SHOWN_SAVE_INTERVAL: u64 = 60;
+// The shortest time between background syncs, in seconds, to stay well within the API limits
+const MIN_SYNC_INTERVAL: u64 = 5 * 60;
 
 struct Timer {
     now: DateTime<Local>,
@@ -39,23 +55,38 @@ struct Timer {
 struct Idle {
     time: f64,
     image: Texture,
+    caption: String,
+    photo_id: Option<String>,
 }
 
 struct Transitioning {
     time: f64,
     image: Texture,
     next_image: Texture,
+    next_caption: String,
+    next_photo_id: Option<String>,
 }
 
 enum State {
+    /// There are no photos to show yet, the message says why
+    Waiting(String),
     Idle(Idle),
     Transitioning(Transitioning),
 }
 
 impl State {
+    /// The photo being shown, or faded to when transitioning
+    fn photo_id(&self) -> Option<String> {
+        match self {
+            State::Waiting(_) => None,
+            State::Idle(Idle { photo_id, .. }) => photo_id.clone(),
+            State::Transitioning(Transitioning { next_photo_id, .. }) => next_photo_id.clone(),
+        }
+    }
+
     fn alpha(&self) -> [f32; 4] {
         let alpha = match self {
-            State::Idle(_) => 0.,
+            State::Waiting(_) | State::Idle(_) => 0.,
             State::Transitioning(Transitioning { time, .. }) => *time as f32,
         };
 
@@ -64,50 +95,125 @@ impl State {
 
     fn alpha2(&self) -> [f32; 4] {
         let alpha = match self {
-            State::Idle(_) => 0.,
+            State::Waiting(_) | State::Idle(_) => 0.,
             State::Transitioning(Transitioning { time, .. }) => *time as f32,
         };
 
         color::alpha(1.0 - alpha)
     }
+
+    /// Change the message shown while waiting for photos
+    fn set_waiting_message(&mut self, message: String) {
+        if let State::Waiting(ref mut waiting) = *self {
+            *waiting = message;
+        }
+    }
 }
 
 fn main() -> Result<(), WallflowerError> {
     env_logger::init();
 
-    let api_key = env::var("FLICKR_API_KEY").expect("FLICKR_API_KEY must be set");
-    let api_secret = env::var("FLICKR_API_SECRET").expect("FLICKR_API_SECRET must be set");
-
-    let client = flickr::Client::new(&api_key, &api_secret);
-    let client = slideshow::load_access_token(client, FLICKR_DATA_FILE)?;
+    let config = config::load(CONFIG_FILE)?;
 
-    // Verify token, and get user info
-    let token_info = client.check_token()?;
-
-    println!("{:?}", token_info);
-
-    slideshow::update_photostream(&token_info.user.nsid, &client)?;
+    let api_key = env::var("FLICKR_API_KEY").expect("FLICKR_API_KEY must be set");
+    // Without the secret only public photos can be shown, but no authorization is needed
+    let api_secret = env::var("FLICKR_API_SECRET").ok();
 
-    // Load the list of available photos
-    let photos = slideshow::available_photos("photos")?;
-    if photos.len() == 0 {
-        panic!("No photos to show"); // TODO: Make nicer
-    }
-    let mut photos = photos.iter().cycle();
+    let retry_policy = flickr::RetryPolicy {
+        max_retries: config.max_retries,
+        ..Default::default()
+    };
+    let http = http::client(&config.http)?;
+    let client = flickr::Client::new(&api_key, api_secret.as_ref().map_or("", String::as_str))
+        .http_client(http.clone())
+        .endpoints(config.flickr_endpoints.clone())
+        .permission(config.permission)
+        .retry_policy(retry_policy)
+        .call_budget(flickr::CallBudget::new(config.calls_per_hour));
+
+    let display = Size {
+        width: config.display_width,
+        height: config.display_height,
+    };
 
     // Start graphics
     let opengl = OpenGL::V2_1;
-    let mut window: GlfwWindow = WindowSettings::new("Wallflower", [1366, 768])
+    let mut window: GlfwWindow = WindowSettings::new("Wallflower", display)
         .exit_on_esc(true)
         //.fullscreen(true)
         .opengl(opengl)
         .build()
         .unwrap();
 
-    let mut state = State::Idle(Idle {
-        time: 0.,
-        image: slideshow::load_photo(&photos.next().unwrap())?,
-    }); // unwrap should be safe because there are elements in the Vec and cycle means it will never return None
+    let assets = Path::new("assets");
+    let ttf = assets.join("ttf");
+    let font = ttf.join("iosevka-ss08-semibold.ttf");
+    let mut glyphs = GlyphCache::new(font, (), TextureSettings::new()).expect("error loading font");
+
+    let mut gl = GlGraphics::new(opengl);
+    let event_settings = EventSettings {
+        max_fps: 24,
+        ups: 24,
+        ..Default::default()
+    };
+    let mut events = Events::new(event_settings);
+
+    let downloader = slideshow::Downloader::new(http.clone(), display, &config.downloads);
+    if config.sync_interval < MIN_SYNC_INTERVAL {
+        println!("sync_interval is too short, syncing every {} seconds", MIN_SYNC_INTERVAL);
+    }
+    let mut authorization = None;
+    // client is None while waiting to be authorized again, or when only showing public photos
+    let (account, mut client, tokens) = if api_secret.is_none() {
+        println!("FLICKR_API_SECRET is not set, only public photos will be shown");
+        (SyncAccount::Public(client), None, None)
+    } else {
+        let token_path = config
+            .token_file
+            .clone()
+            .or_else(token::default_path)
+            .unwrap_or_else(|| PathBuf::from("flickr-token.json"));
+        let mut tokens = token::TokenStore::new(token_path);
+        let key_file = config.token_key_file.as_ref().map(PathBuf::as_path);
+        if let Some(key) = token::load_key(key_file)? {
+            tokens = tokens.key(key)?;
+        }
+        tokens.migrate(LEGACY_TOKEN_FILE)?;
+
+        let client = match tokens.load()? {
+            Some(access_token) => flickr::AuthenticatedClient::new(client, access_token),
+            None => {
+                let mut authorization =
+                    Authorization::start(client, &config, &tokens, SETUP_PROMPT);
+                let setup = run_setup(
+                    &mut window,
+                    &mut events,
+                    &mut gl,
+                    &mut glyphs,
+                    &mut authorization,
+                )?;
+                match setup {
+                    Some(client) => client,
+                    // The window was closed before setup was finished
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        // A token Flickr has since rejected is handled by the main loop when the sync finds it
+        let client = Arc::new(client);
+        (SyncAccount::Authorized(client.clone()), Some(client), Some(tokens))
+    };
+    // Every sync runs in the background so the window keeps responding, including the first
+    let mut sync_events = Some(spawn_sync(account, &config, &downloader, Duration::from_secs(0)));
+
+    // Load the list of available photos, it's updated by the background sync
+    let mut playlist = Playlist::new(Catalog::load("photos")?.paths("photos"));
+    // When each photo was shown, for evicting the least recently shown from the cache
+    let mut shown = ShownLog::load("photos")?;
+    let mut shown_saved = Instant::now();
+    // The first photo is loaded by the first update
+    let mut state = State::Waiting(String::from(SYNCING_MESSAGE));
 
     // Start the time updater thread
     let timer = Arc::new(Mutex::new(Timer {
@@ -126,7 +232,7 @@ fn main() -> Result<(), WallflowerError> {
 
     let bg_timer = timer.clone();
     let weather_update = Duration::from_secs(5 * 60);
-    let bom = weather::Client::new();
+    let bom = weather::Client::new(http.clone()).observations_url(&config.observations_url);
     thread::spawn(move || loop {
         let observation = bom.observations().ok().and_then(statusbar::latest_observation);
         {
@@ -137,33 +243,85 @@ fn main() -> Result<(), WallflowerError> {
         sleep(weather_update);
     });
 
-    let assets = Path::new("assets");
-    let ttf = assets.join("ttf");
-    let font = ttf.join("iosevka-ss08-semibold.ttf");
-    let mut glyphs = GlyphCache::new(font, (), TextureSettings::new()).expect("error loading font");
-
-    let mut gl = GlGraphics::new(opengl);
-    let event_settings = EventSettings {
-        max_fps: 24,
-        ups: 24,
-        ..Default::default()
-    };
-    let mut events = Events::new(event_settings);
-
     while let Some(event) = events.next(&mut window) {
         let window_size = window.size();
 
         if let Some(args) = event.update_args() {
+            let authorized = match authorization {
+                Some(ref mut authorization) => authorization.poll()?,
+                None => None,
+            };
+            if let Some(authorized) = authorized {
+                authorization = None;
+                let authorized = Arc::new(authorized);
+                client = Some(authorized.clone());
+                let account = SyncAccount::Authorized(authorized);
+                let now = Duration::from_secs(0);
+                sync_events = Some(spawn_sync(account, &config, &downloader, now));
+            }
+
+            // New photos join the playlist and removed ones leave it, the transition in progress
+            // already has its images loaded
+            let mut revoked = false;
+            if let Some(ref sync_events) = sync_events {
+                for event in sync_events.try_iter() {
+                    match event {
+                        SyncEvent::Photos(photos) => {
+                            if photos.is_empty() {
+                                state.set_waiting_message(String::from(NO_PHOTOS_MESSAGE));
+                            }
+                            playlist.update(photos);
+                        }
+                        SyncEvent::Failed(err) => {
+                            println!("sync failed: {:?}", err);
+                            state.set_waiting_message(format!("Unable to sync photos: {}", err));
+                            revoked = revoked || err.is_invalid_token();
+                        }
+                    }
+                }
+            }
+            if revoked {
+                sync_events = None;
+                if let (Some(rejected), Some(tokens)) = (client.take(), tokens.as_ref()) {
+                    let client = rejected.client().clone();
+                    authorization = Some(reauthorize(client, &config, tokens)?);
+                }
+            }
+
             state = match state {
-                State::Idle(mut idle) => {
-                    if idle.time > 5. {
-                        println!("Transitioning!");
-                        State::Transitioning(Transitioning {
+                State::Waiting(message) => match load_next(&mut playlist) {
+                    Some((image, info)) => {
+                        if let Some(ref info) = info {
+                            shown.mark(&info.id, Utc::now());
+                        }
+                        State::Idle(Idle {
                             time: 0.,
-                            image: idle.image,
-                            next_image: slideshow::load_photo(photos.next().unwrap())
-                                .expect("error loading image FIXME"),
+                            image,
+                            caption: statusbar::format_caption(&info),
+                            photo_id: info.map(|info| info.id),
                         })
+                    }
+                    None => State::Waiting(message),
+                },
+                State::Idle(mut idle) => {
+                    if idle.time > 5. {
+                        match load_next(&mut playlist) {
+                            Some((next_image, next_info)) => {
+                                println!("Transitioning!");
+                                State::Transitioning(Transitioning {
+                                    time: 0.,
+                                    image: idle.image,
+                                    next_image,
+                                    next_caption: statusbar::format_caption(&next_info),
+                                    next_photo_id: next_info.map(|info| info.id),
+                                })
+                            }
+                            // Keep showing the current photo until there's another to show
+                            None => {
+                                idle.time = 0.;
+                                State::Idle(idle)
+                            }
+                        }
                     } else {
                         idle.time += args.dt;
                         State::Idle(idle)
@@ -172,9 +330,20 @@ fn main() -> Result<(), WallflowerError> {
                 State::Transitioning(mut transitioning) => {
                     if transitioning.time > 1. {
                         println!("Idling!");
+                        if let Some(ref photo_id) = transitioning.next_photo_id {
+                            shown.mark(photo_id, Utc::now());
+                        }
+                        if shown_saved.elapsed() > Duration::from_secs(SHOWN_SAVE_INTERVAL) {
+                            if let Err(err) = shown.save("photos") {
+                                println!("unable to save shown photos: {:?}", err);
+                            }
+                            shown_saved = Instant::now();
+                        }
                         State::Idle(Idle {
                             time: 0.,
                             image: transitioning.next_image,
+                            caption: transitioning.next_caption,
+                            photo_id: transitioning.next_photo_id,
                         })
                     } else {
                         transitioning.time += args.dt;
@@ -189,6 +358,27 @@ fn main() -> Result<(), WallflowerError> {
                 Button::Keyboard(Key::Left) => println!("left"),
                 Button::Keyboard(Key::Right) => println!("right"),
                 Button::Keyboard(Key::Space) => println!("TODO pause"),
+                Button::Keyboard(Key::F) => {
+                    if let (Some(client), Some(photo_id)) = (client.clone(), state.photo_id()) {
+                        thread::spawn(move || match client.add_favorite(&photo_id) {
+                            Ok(()) => println!("faved {}", photo_id),
+                            Err(err) => println!("unable to fave {}: {:?}", photo_id, err),
+                        });
+                    }
+                }
+                Button::Keyboard(Key::P) => {
+                    if let (Some(client), Some(photo_id)) = (client.clone(), state.photo_id()) {
+                        let album = config.picks_album.clone();
+                        thread::spawn(move || {
+                            match slideshow::add_to_album(&client, &album, &photo_id) {
+                                Ok(()) => println!("added {} to {}", photo_id, album),
+                                Err(err) => {
+                                    println!("unable to add {} to {}: {:?}", photo_id, album, err)
+                                }
+                            }
+                        });
+                    }
+                }
                 _ => (),
             }
         }
@@ -198,6 +388,17 @@ fn main() -> Result<(), WallflowerError> {
                 clear([0.0; 4], gfx);
 
                 match state {
+                    State::Waiting(ref message) => {
+                        Text::new_color([1.0, 1.0, 1.0, 0.75], 24)
+                            .draw(
+                                message,
+                                &mut glyphs,
+                                &context.draw_state,
+                                context.transform.trans(20., 50.),
+                                gfx,
+                            )
+                            .expect("text drawing error");
+                    }
                     State::Idle(ref idle) => {
                         let (im_width, im_height) = idle.image.get_size();
                         let image_size = Size {
@@ -291,9 +492,241 @@ fn main() -> Result<(), WallflowerError> {
                         gfx,
                     )
                     .expect("text drawing error");
+
+                // Draw the caption of the current photo above the status bar
+                if let State::Idle(ref idle) = state {
+                    if !idle.caption.is_empty() {
+                        let caption_size = 20;
+                        let transform = context.transform.trans(
+                            10.0,
+                            window_size.height as f64 - (text_size as f64 * 2.) - 10.,
+                        );
+                        Text::new_color([1.0, 1.0, 1.0, 0.75], caption_size)
+                            .draw(
+                                &idle.caption,
+                                &mut glyphs,
+                                &context.draw_state,
+                                transform,
+                                gfx,
+                            )
+                            .expect("text drawing error");
+                    }
+                }
+
+                // Prompt to authorize again in the corner while the cached photos are shown
+                if let Some(ref authorization) = authorization {
+                    Rectangle::new([0., 0., 0., 0.75]).draw(
+                        [0., 0., window_size.width as f64, text_size as f64 * 2.],
+                        &context.draw_state,
+                        context.transform,
+                        gfx,
+                    );
+                    Text::new_color([1.0, 1.0, 1.0, 0.75], 20)
+                        .draw(
+                            &authorization.message,
+                            &mut glyphs,
+                            &context.draw_state,
+                            context.transform.trans(10., text_size as f64 + 10.),
+                            gfx,
+                        )
+                        .expect("text drawing error");
+
+                    if let Some(ref code) = authorization.code {
+                        let side = f64::from(window_size.width.min(window_size.height)) * 0.3;
+                        let left = window_size.width as f64 - side - 20.;
+                        let top = window_size.height as f64 - (text_size as f64 * 2.) - side - 20.;
+                        draw_code(code, [left, top, side], context.transform, gfx);
+                    }
+                }
             });
         }
     }
 
     Ok(())
 }
+
+/// Discard the access token Flickr rejected and start authorizing the frame again
+fn reauthorize(
+    client: flickr::Client,
+    config: &config::Config,
+    tokens: &TokenStore,
+) -> Result<Authorization, WallflowerError> {
+    println!("Flickr rejected the access token, authorizing again");
+    tokens.remove()?;
+    Ok(Authorization::start(client, config, tokens, REVOKED_PROMPT))
+}
+
+/// Keep syncing the sources in the background, starting after `delay`
+fn spawn_sync(
+    account: SyncAccount,
+    config: &config::Config,
+    downloader: &slideshow::Downloader,
+    delay: Duration,
+) -> Receiver<SyncEvent> {
+    slideshow::spawn_sync(
+        account,
+        config.sources.clone(),
+        downloader.clone(),
+        config.cache.clone(),
+        delay,
+        sync_interval(config),
+    )
+}
+
+/// Time between background syncs, which is at least `MIN_SYNC_INTERVAL`
+fn sync_interval(config: &config::Config) -> Duration {
+    Duration::from_secs(config.sync_interval.max(MIN_SYNC_INTERVAL))
+}
+
+/// Load the next photo in the playlist, skipping any that can't be loaded, for example because
+/// they've been removed from the cache since the playlist was last updated
+fn load_next(playlist: &mut Playlist) -> Option<(Texture, Option<slideshow::PhotoInfo>)> {
+    for _ in 0..playlist.len() {
+        let path = match playlist.next() {
+            Some(path) => path.to_path_buf(),
+            None => return None,
+        };
+        match slideshow::load_photo(&path) {
+            Ok(image) => return Some((image, slideshow::load_photo_info(&path))),
+            Err(err) => println!("unable to load {:?}: {:?}", path, err),
+        }
+    }
+
+    None
+}
+
+/// The on-screen authorization flow, used on the first run and when the token is revoked
+struct Authorization {
+    events: Receiver<SetupEvent>,
+    tokens: TokenStore,
+    prompt: &'static str,
+    code: Option<setup::Code>,
+    url: String,
+    message: String,
+}
+
+impl Authorization {
+    fn start(
+        client: flickr::Client,
+        config: &config::Config,
+        tokens: &TokenStore,
+        prompt: &'static str,
+    ) -> Self {
+        Authorization {
+            events: setup::authorize(client, config.auth_callback.clone()),
+            tokens: tokens.clone(),
+            prompt,
+            code: None,
+            url: String::new(),
+            message: String::from("Connecting to Flickr..."),
+        }
+    }
+
+    /// Handle progress from the background thread, saving the token once authorized
+    fn poll(&mut self) -> Result<Option<flickr::AuthenticatedClient>, WallflowerError> {
+        for event in self.events.try_iter() {
+            match event {
+                SetupEvent::AuthorizationUrl(authorization_url) => {
+                    self.code = setup::Code::new(&authorization_url);
+                    self.url = authorization_url.to_string();
+                    self.message = String::from(self.prompt);
+                }
+                SetupEvent::Authorized(client) => {
+                    self.tokens.save(client.access_token())?;
+                    return Ok(Some(client));
+                }
+                SetupEvent::Failed(err) => {
+                    println!("{:?}", err);
+                    self.code = None;
+                    self.message = format!("Authorization failed: {}. Trying again...", err);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Draw `code` as a square with its top left corner and side length given by `square`
+///
+/// The code is surrounded by the 4 module quiet zone scanners need.
+fn draw_code(
+    code: &setup::Code,
+    square: [f64; 3],
+    transform: math::Matrix2d,
+    gfx: &mut GlGraphics,
+) {
+    let [left, top, side] = square;
+    let quiet_zone = 4;
+    let modules = (code.width() + quiet_zone * 2) as f64;
+    let module = (side / modules).floor().max(1.);
+    let side = module * modules;
+
+    rectangle([1.0; 4], [left, top, side, side], transform, gfx);
+    for y in 0..code.width() {
+        for x in 0..code.width() {
+            if code.is_dark(x, y) {
+                let rect = [
+                    left + (x + quiet_zone) as f64 * module,
+                    top + (y + quiet_zone) as f64 * module,
+                    module,
+                    module,
+                ];
+                rectangle([0., 0., 0., 1.], rect, transform, gfx);
+            }
+        }
+    }
+}
+
+/// Show the authorization screen until the app has been authorized
+///
+/// The authorization URL is shown as a QR code so it can be opened on a phone. Returns `None`
+/// if the window is closed first.
+fn run_setup(
+    window: &mut GlfwWindow,
+    events: &mut Events,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    authorization: &mut Authorization,
+) -> Result<Option<flickr::AuthenticatedClient>, WallflowerError> {
+    while let Some(event) = events.next(window) {
+        if let Some(client) = authorization.poll()? {
+            return Ok(Some(client));
+        }
+
+        if let Some(args) = event.render_args() {
+            let window_size = window.size();
+            gl.draw(args.viewport(), |context, gfx| {
+                clear([0.0; 4], gfx);
+
+                if let Some(ref code) = authorization.code {
+                    let side = f64::from(window_size.width.min(window_size.height)) * 0.6;
+                    let left = (f64::from(window_size.width) - side) / 2.;
+                    let top = (f64::from(window_size.height) - side) / 2.;
+                    draw_code(code, [left, top, side], context.transform, gfx);
+                }
+
+                Text::new_color([1.0, 1.0, 1.0, 0.75], 24)
+                    .draw(
+                        &authorization.message,
+                        glyphs,
+                        &context.draw_state,
+                        context.transform.trans(20., 50.),
+                        gfx,
+                    )
+                    .expect("text drawing error");
+                Text::new_color([1.0, 1.0, 1.0, 0.5], 14)
+                    .draw(
+                        &authorization.url,
+                        glyphs,
+                        &context.draw_state,
+                        context.transform.trans(20., window_size.height as f64 - 20.),
+                        gfx,
+                    )
+                    .expect("text drawing error");
+            });
+        }
+    }
+
+    Ok(None)
+}
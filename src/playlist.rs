@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// The order the cached photos are shown in, kept up to date as the catalog changes
+///
+/// Photos are shown in turn, starting again from the first after the last. When the catalog
+/// changes, photos that were added are shown next and photos that were removed are dropped
+/// without changing the order of the rest.
+#[derive(Debug, Default)]
+pub struct Playlist {
+    /// The photos in the order they're coming up
+    photos: Vec<PathBuf>,
+    /// Index of the next photo to show
+    next: usize,
+}
+
+impl Playlist {
+    pub fn new(photos: Vec<PathBuf>) -> Self {
+        Playlist { photos, next: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photos.is_empty()
+    }
+
+    /// The photo to show next, `None` if there aren't any
+    pub fn next(&mut self) -> Option<&Path> {
+        if self.photos.is_empty() {
+            return None;
+        }
+
+        let index = self.next % self.photos.len();
+        self.next = index + 1;
+        Some(&self.photos[index])
+    }
+
+    /// Replace the photos with the ones now in the catalog
+    pub fn update(&mut self, photos: Vec<PathBuf>) {
+        let known = self.photos.iter().cloned().collect::<HashSet<_>>();
+        let current = photos.iter().cloned().collect::<HashSet<_>>();
+
+        let next = if self.photos.is_empty() {
+            0
+        } else {
+            self.next % self.photos.len()
+        };
+        let (played, upcoming) = self.photos.split_at(next);
+        let mut updated = photos
+            .into_iter()
+            .filter(|photo| !known.contains(photo))
+            .collect::<Vec<_>>();
+        updated.extend(
+            upcoming
+                .iter()
+                .chain(played)
+                .filter(|photo| current.contains(*photo))
+                .cloned(),
+        );
+
+        self.photos = updated;
+        self.next = 0;
+    }
+}
+
+#[test]
+fn test_playlist() {
+    let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut playlist = Playlist::new(paths(&["a", "b", "c", "d"]));
+    assert_eq!(playlist.next(), Some(Path::new("a")));
+    assert_eq!(playlist.next(), Some(Path::new("b")));
+
+    // e is added, b and c are removed
+    playlist.update(paths(&["a", "d", "e"]));
+    assert_eq!(playlist.len(), 3);
+    assert_eq!(playlist.next(), Some(Path::new("e")));
+    assert_eq!(playlist.next(), Some(Path::new("d")));
+    assert_eq!(playlist.next(), Some(Path::new("a")));
+    assert_eq!(playlist.next(), Some(Path::new("e")));
+
+    playlist.update(Vec::new());
+    assert!(playlist.is_empty());
+    assert_eq!(playlist.next(), None);
+}
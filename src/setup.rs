@@ -0,0 +1,88 @@
+use qrcode::{Color, QrCode};
+use reqwest::Url;
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use config::CallbackConfig;
+use error::FlickrError;
+use flickr::{self, AccessToken, AuthenticatedClient, CallbackListener, FlickrResult};
+
+/// Progress of the first run authorization
+pub enum SetupEvent {
+    /// The user needs to visit this URL to authorize the frame
+    AuthorizationUrl(Url),
+    Authorized(AuthenticatedClient),
+    /// The attempt failed, another is started after a short delay
+    Failed(FlickrError),
+}
+
+/// Authorize `client` in the background, reporting progress on the returned channel
+///
+/// Failed attempts, including ones that time out, are retried with a new request token until
+/// the app is authorized or the receiver is dropped.
+pub fn authorize(client: flickr::Client, config: CallbackConfig) -> Receiver<SetupEvent> {
+    let (tx, rx) = channel();
+
+    thread::spawn(move || loop {
+        match authorize_once(&client, &config, &tx) {
+            Ok(access_token) => {
+                let _ = tx.send(SetupEvent::Authorized(AuthenticatedClient::new(
+                    client,
+                    access_token,
+                )));
+                return;
+            }
+            Err(err) => {
+                if tx.send(SetupEvent::Failed(err)).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_secs(10));
+            }
+        }
+    });
+
+    rx
+}
+
+fn authorize_once(
+    client: &flickr::Client,
+    config: &CallbackConfig,
+    tx: &Sender<SetupEvent>,
+) -> FlickrResult<AccessToken> {
+    let listener = CallbackListener::bind(&config.listen, config.host.as_ref().map(String::as_str))?;
+    client.authorize_with_callback(&listener, Duration::from_secs(config.timeout), |url| {
+        let _ = tx.send(SetupEvent::AuthorizationUrl(url.clone()));
+    })
+}
+
+/// The modules of a QR code, for drawing on screen
+pub struct Code {
+    width: usize,
+    dark: Vec<bool>,
+}
+
+impl Code {
+    pub fn new(url: &Url) -> Option<Self> {
+        let code = QrCode::new(url.as_str()).ok()?;
+        let dark = code.to_colors()
+            .into_iter()
+            .map(|color| color == Color::Dark)
+            .collect();
+
+        Some(Code {
+            width: code.width(),
+            dark,
+        })
+    }
+
+    /// Number of modules along each side, not including the quiet zone
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.dark[y * self.width + x]
+    }
+}
@@ -1,4 +1,4 @@
-use reqwest::{self, Url};
+use reqwest::{self, header, mime, StatusCode, Url};
 use threadpool::ThreadPool;
 use piston::window::{Size};
 use opengl_graphics::*;
@@ -8,33 +8,498 @@ use serde_json;
 
 use std;
 use std::borrow::Borrow;
-use std::fs::{self, File};
-use std::io;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{self, Instant};
 use std::ffi::OsStr;
 use image::{self, Pixel, Rgba};
 use graphics::color::gamma_srgb_to_linear;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use exif::{self, In, Tag, Value};
 
-use flickr::{self, AccessToken, AuthenticatedClient, Photo};
+use catalog::{CacheConfig, Catalog, Room, ShownLog};
+use flickr::{self, AuthenticatedClient, ContentType, Exif, FlickrResult, Photo,
+             PhotoSize, PhotosRequest, SearchRequest, METADATA_EXTRAS, SIZE_EXTRAS};
+use error::FlickrError;
 use WallflowerError;
 
+/// A collection of Flickr photos to show in the slideshow
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    /// All of the authenticated user's photos
+    Photostream,
+    /// A single photoset (album) owned by the authenticated user
+    Photoset { id: String },
+    /// Photos marked as a favorite by `user_id`, or the authenticated user if not set
+    Favorites { user_id: Option<String> },
+    /// The photo pool of a group
+    Group { id: String },
+    /// Recent uploads from the authenticated user's contacts
+    Contacts {
+        #[serde(default)]
+        just_friends: bool,
+        #[serde(default)]
+        include_self: bool,
+    },
+    /// A saved search
+    Search(SearchRequest),
+    /// The public photos of `user_id`, doesn't need authorization
+    PublicPhotos { user_id: String },
+    /// The photos picked for Explore over the last `days` days, doesn't need authorization
+    Interestingness {
+        #[serde(default = "default_explore_days")]
+        days: u32,
+    },
+}
+
+fn default_explore_days() -> u32 {
+    1
+}
+
+impl Source {
+    /// Short description of the source, recorded in the catalog
+    pub fn name(&self) -> String {
+        match *self {
+            Source::Photostream => String::from("photostream"),
+            Source::Photoset { ref id } => format!("photoset {}", id),
+            Source::Favorites { user_id: Some(ref user_id) } => format!("favorites of {}", user_id),
+            Source::Favorites { user_id: None } => String::from("favorites"),
+            Source::Group { ref id } => format!("group {}", id),
+            Source::Contacts { .. } => String::from("contacts"),
+            Source::Search(ref search) => format!("search {}", search.describe()),
+            Source::PublicPhotos { ref user_id } => format!("public photos of {}", user_id),
+            Source::Interestingness { .. } => String::from("interestingness"),
+        }
+    }
+
+    /// The options for listing the photos of the source
+    ///
+    /// Only the photostream can be limited by date and content type, the other methods accept
+    /// just the extras and page size, and contacts not even that.
+    fn request(&self) -> PhotosRequest {
+        let mut extras = SIZE_EXTRAS.to_vec();
+        extras.extend_from_slice(&METADATA_EXTRAS);
+        let request = PhotosRequest::new().extras(&extras);
+        match *self {
+            Source::Photostream => request
+                .min_taken_date(Utc.timestamp(1388494800, 0))
+                .content_type(ContentType::Photos)
+                .per_page(100),
+            Source::Contacts { .. } => request,
+            _ => request.per_page(100),
+        }
+    }
+}
+
+/// Who photos are synced for
+#[derive(Clone, Copy)]
+pub enum Account<'a> {
+    /// Only public photos are available, requested with just the API key
+    Public(&'a flickr::Client),
+    /// The user the frame was authorized by, whose NSID is `user_id`
+    Authorized {
+        client: &'a AuthenticatedClient,
+        user_id: &'a str,
+    },
+}
+
+impl<'a> Account<'a> {
+    fn exif(&self, photo_id: &str) -> FlickrResult<Exif> {
+        match *self {
+            Account::Public(client) => client.exif(photo_id),
+            Account::Authorized { client, .. } => client.exif(photo_id),
+        }
+    }
+}
+
+/// Details of a cached photo, stored alongside it in a JSON file with the same name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhotoInfo {
+    pub id: String,
+    pub title: String,
+    pub owner: Option<String>,
+    pub owner_name: Option<String>,
+    pub date_taken: Option<NaiveDateTime>,
+    pub date_upload: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub license: Option<u32>,
+    pub views: Option<u32>,
+    /// Camera settings, looked up after the photo is downloaded
+    pub exif: Option<Exif>,
+}
+
+impl<'a> From<&'a Photo> for PhotoInfo {
+    fn from(photo: &'a Photo) -> Self {
+        PhotoInfo {
+            id: photo.id.clone(),
+            title: photo.title.clone(),
+            owner: photo.owner.clone(),
+            owner_name: photo.owner_name.clone(),
+            date_taken: photo.date_taken,
+            date_upload: photo.date_upload,
+            description: photo.description.clone(),
+            tags: photo.tags.clone(),
+            latitude: photo.latitude,
+            longitude: photo.longitude,
+            license: photo.license,
+            views: photo.views,
+            exif: None,
+        }
+    }
+}
+
 enum Dimension {
     Width(u32),
     Height(u32),
 }
 
-fn download_file(url: &Url, path: &Path) -> Result<(), WallflowerError> {
-    let mut file = File::create(path)?;
-    // TODO: Check that content type suggests it's actually an image
-    // FIXME: reqwest::get creates a new client for each request. Ideally each thread would have its own client and that would be reused for each request that worker serviced
-    reqwest::get(url.as_str())?.copy_to(&mut file)?;
+/// How photos are downloaded into the cache
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Number of photos to download at once
+    pub workers: usize,
+    /// Limit on the combined speed of the downloads, in bytes per second
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            workers: 8,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+/// Settings shared by the workers that download photos into the cache
+#[derive(Clone)]
+pub struct Downloader {
+    http: reqwest::Client,
+    display: Size,
+    workers: usize,
+    throttle: Option<Arc<Throttle>>,
+    /// Room left in the cache for new photos, shared by the workers of a sync
+    room: Option<Arc<Mutex<Room>>>,
+}
+
+impl Downloader {
+    /// Download photos with `http`, choosing the size of each one to suit `display`
+    pub fn new(http: reqwest::Client, display: Size, config: &DownloadConfig) -> Self {
+        Downloader {
+            http,
+            display,
+            workers: config.workers.max(1),
+            throttle: config
+                .max_bytes_per_sec
+                .filter(|&rate| rate > 0)
+                .map(|rate| Arc::new(Throttle::new(rate))),
+            room: None,
+        }
+    }
+
+    /// A downloader that stops downloading new photos once they no longer fit in `room`
+    fn with_room(&self, room: Room) -> Self {
+        Downloader {
+            room: Some(Arc::new(Mutex::new(room))),
+            ..self.clone()
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.room.as_ref().map_or(false, |room| {
+            room.lock().expect("room lock poisoned").is_full()
+        })
+    }
+
+    /// Set aside room in the cache for a photo of `bytes` bytes
+    fn reserve(&self, bytes: u64) -> Option<Reservation> {
+        if self.take_room(1, bytes) {
+            Some(Reservation {
+                downloader: self,
+                bytes,
+                kept: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn take_room(&self, photos: usize, bytes: u64) -> bool {
+        self.room.as_ref().map_or(true, |room| {
+            room.lock().expect("room lock poisoned").reserve(photos, bytes)
+        })
+    }
+
+    fn release_room(&self, photos: usize, bytes: u64) {
+        if let Some(ref room) = self.room {
+            room.lock().expect("room lock poisoned").release(photos, bytes);
+        }
+    }
+}
+
+/// Room set aside in the cache for a download, given back if the download fails
+struct Reservation<'a> {
+    downloader: &'a Downloader,
+    bytes: u64,
+    kept: bool,
+}
+
+impl<'a> Reservation<'a> {
+    /// Set aside `extra` bytes more, returning false if they don't fit
+    fn grow(&mut self, extra: u64) -> bool {
+        let fits = self.downloader.take_room(0, extra);
+        if fits {
+            self.bytes += extra;
+        }
+        fits
+    }
+
+    /// Keep the room now the photo is in the cache
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl<'a> Drop for Reservation<'a> {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.downloader.release_room(1, self.bytes);
+        }
+    }
+}
+
+/// The result of syncing a source
+#[derive(Default)]
+struct Synced {
+    /// Paths of the source's cached photos
+    paths: Vec<PathBuf>,
+    /// Ids of the photos that couldn't be downloaded, and why
+    failed: Vec<(String, String)>,
+    /// Number of new photos that weren't downloaded as the cache is full
+    skipped: usize,
+}
+
+/// Limits the combined rate of the downloads sharing it
+struct Throttle {
+    bytes_per_sec: u64,
+    /// When the transfers so far will have been paid for
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Throttle {
+            bytes_per_sec,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Account for `bytes` that were just transferred, sleeping until they're within the rate
+    fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut next = self.next.lock().expect("throttle lock poisoned");
+            let now = Instant::now();
+            if *next < now {
+                *next = now;
+            }
+            *next += transfer_time(bytes, self.bytes_per_sec);
+            *next - now
+        };
+        thread::sleep(wait);
+    }
+}
+
+/// How long `bytes` take to transfer at `bytes_per_sec`
+fn transfer_time(bytes: u64, bytes_per_sec: u64) -> time::Duration {
+    let nanos = (bytes % bytes_per_sec) * 1_000_000_000 / bytes_per_sec;
+    time::Duration::new(bytes / bytes_per_sec, nanos as u32)
+}
+
+/// Copy `reader` to `writer`, pausing after each chunk to stay within `throttle`
+fn copy_throttled<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    throttle: Option<&Throttle>,
+) -> io::Result<u64> {
+    let mut buf = [0; 16 * 1024];
+    let mut copied = 0;
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..len])?;
+        copied += len as u64;
+        if let Some(throttle) = throttle {
+            throttle.consume(len as u64);
+        }
+    }
+}
+
+/// Download `url` to `path`, only moving it into place once it's known to be a complete image
+///
+/// The download is written to a `.part` file next to `path` so an interrupted transfer is never
+/// mistaken for a cached photo. The `.part` file is kept if the transfer is cut short and the
+/// next attempt resumes from where it got to.
+fn download_file(downloader: &Downloader, url: &Url, path: &Path) -> Result<(), WallflowerError> {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
 
+    let reservation = download_verified(downloader, url, &part)?;
+    fs::rename(&part, path)?;
+    reservation.keep();
     Ok(())
 }
 
-fn do_fetch_photo(url: &Url) -> Result<(), WallflowerError> {
+/// Download `url` to `part` and check it, returning the room made for it in the cache
+fn download_verified<'a>(
+    downloader: &'a Downloader,
+    url: &Url,
+    part: &Path,
+) -> Result<Reservation<'a>, WallflowerError> {
+    let invalid = |reason: String| WallflowerError::InvalidDownload {
+        url: url.to_string(),
+        reason,
+    };
+    let discard = |reason: String| {
+        let _ = fs::remove_file(part);
+        Err(invalid(reason))
+    };
+
+    let offset = fs::metadata(part).map(|metadata| metadata.len()).unwrap_or(0);
+    let mut request = downloader.http.get(url.clone());
+    if offset > 0 {
+        request.header(header::Range::Bytes(vec![header::ByteRangeSpec::AllFrom(offset)]));
+    }
+    let mut res = request.send()?;
+
+    // Servers that don't support ranges send the whole image
+    let resumed = match res.status() {
+        StatusCode::PartialContent => match res.headers().get::<header::ContentRange>() {
+            Some(&header::ContentRange(header::ContentRangeSpec::Bytes {
+                range: Some((start, _)),
+                ..
+            })) if start == offset =>
+            {
+                true
+            }
+            _ => return discard(String::from("unexpected content range")),
+        },
+        StatusCode::RangeNotSatisfiable => {
+            return discard(format!("unable to resume from {} bytes", offset))
+        }
+        status if status.is_success() => false,
+        status => return Err(invalid(format!("HTTP status {}", status))),
+    };
+    match res.headers().get::<header::ContentType>() {
+        Some(content_type) if content_type.type_() == mime::IMAGE => (),
+        Some(content_type) => return Err(invalid(format!("content type {}", content_type))),
+        None => return Err(invalid(String::from("no content type"))),
+    }
+
+    let start = if resumed { offset } else { 0 };
+    let expected = res.headers()
+        .get::<header::ContentLength>()
+        .map(|length| start + length.0);
+    // Without a length room is made for the size once it's known
+    let mut reservation = downloader
+        .reserve(expected.unwrap_or(start))
+        .ok_or(WallflowerError::CacheFull)?;
+
+    let mut file = if resumed {
+        println!("{} -> resuming from {} bytes", url, offset);
+        OpenOptions::new().append(true).open(part)?
+    } else {
+        File::create(part)?
+    };
+    let throttle = downloader.throttle.as_ref().map(|throttle| &**throttle);
+    // Anything received before an error is kept to resume from
+    let written = start + copy_throttled(&mut res, &mut file, throttle)?;
+    file.sync_all()?;
+    match expected {
+        Some(expected) if written < expected => {
+            return Err(invalid(format!("received {} of {} bytes", written, expected)))
+        }
+        Some(expected) if written > expected => {
+            return discard(format!("received {} bytes, expected {}", written, expected))
+        }
+        _ => (),
+    }
+
+    // Decoding the whole image also catches truncated files when there's no Content-Length
+    if let Err(err) = decode_jpeg(part) {
+        return discard(format!("not a valid image: {}", err));
+    }
+    if written > reservation.bytes {
+        let extra = written - reservation.bytes;
+        if !reservation.grow(extra) {
+            let _ = fs::remove_file(part);
+            return Err(WallflowerError::CacheFull);
+        }
+    }
+
+    Ok(reservation)
+}
+
+/// Decode the JPEG at `path`, which can't be left to `image::open` as it goes by the extension
+fn decode_jpeg(path: &Path) -> image::ImageResult<DynamicImage> {
+    let file = File::open(path)?;
+    image::load(io::BufReader::new(file), image::ImageFormat::JPEG)
+}
+
+/// Pick the smallest size that fills the display without being scaled up, falling back to the
+/// largest size available
+pub fn best_size(sizes: &[PhotoSize], display: Size) -> Option<&PhotoSize> {
+    // Originals may not be JPEGs, which available_photos would skip
+    let candidates = sizes
+        .iter()
+        .filter(|size| size.url.path().ends_with(".jpg"))
+        .collect::<Vec<_>>();
+
+    let best = candidates
+        .iter()
+        .find(|size| covers_display(size, display))
+        .or_else(|| candidates.last())
+        .map(|size| *size);
+    best
+}
+
+fn covers_display(size: &PhotoSize, display: Size) -> bool {
+    // Mirrors zoom_for_image, which scales photos by their largest dimension
+    let size = Size {
+        width: size.width,
+        height: size.height,
+    };
+    match largest_dimension(size) {
+        Dimension::Width(width) => width >= display.width,
+        Dimension::Height(height) => height >= display.height,
+    }
+}
+
+/// Download `photo` if it is not already cached, returning its path and whether it still needs
+/// EXIF data
+fn do_fetch_photo(
+    photo: &Photo,
+    downloader: &Downloader,
+) -> Result<(PathBuf, bool), WallflowerError> {
     // let path = Path::new("photos");
+    let url = &best_size(&photo.sizes, downloader.display)
+        .ok_or_else(|| WallflowerError::NoSuitableSize(photo.id.clone()))?
+        .url;
     let percent_encoded_path = url.path();
     let cow = percent_encoding::percent_decode(percent_encoded_path.as_bytes()).decode_utf8()?;
     let path: &str = cow.borrow();
@@ -53,76 +518,422 @@ fn do_fetch_photo(url: &Url) -> Result<(), WallflowerError> {
 
     if storage_path.is_file() {
         println!("{} -> exists", url);
-        Ok(())
+    } else if downloader.is_full() {
+        return Err(WallflowerError::CacheFull);
     } else {
         // download the file
         println!("{} -> downloading", url);
-        download_file(url, &storage_path)
+        download_file(downloader, url, &storage_path)?;
     }
+
+    // Always rewrite the info as the title or owner name may have changed, EXIF data is kept as
+    // it costs an extra request to look up. Only written once the image is in place so a failed
+    // download doesn't leave info behind.
+    let mut info = PhotoInfo::from(photo);
+    info.exif = load_photo_info(&storage_path).and_then(|info| info.exif);
+    save_photo_info(&storage_path, &info)?;
+
+    let needs_exif = info.exif.is_none();
+    Ok((storage_path, needs_exif))
 }
 
-fn fetch_photo(photo: Photo, tx: std::sync::mpsc::Sender<Result<(), WallflowerError>>) {
-    tx.send(do_fetch_photo(&photo.url_k))
+fn fetch_photo(
+    photo: Photo,
+    downloader: Downloader,
+    tx: std::sync::mpsc::Sender<(String, Result<(PathBuf, bool), WallflowerError>)>,
+) {
+    let result = do_fetch_photo(&photo, &downloader);
+    tx.send((photo.id, result))
         .expect("error sending to channel");
 }
 
-pub fn update_photostream(user_id: &str, client: &AuthenticatedClient) -> Result<(), WallflowerError> {
-    // Request list of photos from Flickr
-    // Download the ones that aren't in the cache
-    // (optional) Clean up old images
-    // Generate new JSON, move into place atomically
+/// Look up the camera settings of the cached photo at `path` and save them in its info
+///
+/// Flickr strips EXIF data from the smaller sizes so it is requested from the API first, falling
+/// back to the file itself if the owner has hidden their EXIF data. If the request fails for
+/// another reason the info is left without EXIF so the next sync tries again.
+fn update_exif(account: Account, path: &Path) -> Result<(), WallflowerError> {
+    let mut info = match load_photo_info(path) {
+        Some(info) => info,
+        None => return Ok(()),
+    };
 
-    let pool = ThreadPool::new(8);
-    let (tx, rx) = channel();
+    let exif = match account.exif(&info.id) {
+        Ok(exif) => exif,
+        Err(FlickrError::ExifHidden) => read_exif(path).unwrap_or_default(),
+        Err(err) => {
+            println!("{}: unable to get EXIF from Flickr: {}", info.id, err);
+            return Ok(());
+        }
+    };
+    info.exif = Some(exif);
 
-    // Check the last 500 photos
-    // TODO: photos page="2" pages="89" perpage="10" total="881">
-    // Stop if there are fewer than 5 pages
-    for page in 1..3 {
-        let arguments = [
-            ("min_taken_date", "1388494800".to_string()),
-            ("content_type", "1".to_string()), // Photos only
-            ("per_page", "100".to_string()),
-            ("page", page.to_string()),
-            ("extras", "url_k".to_string()),
-        ];
-        let photos = client.photos(user_id, &arguments)?;
+    save_photo_info(path, &info)
+}
 
-        //println!("{:?}", photos);
+/// Read camera settings from the EXIF data embedded in a photo
+pub fn read_exif<P: AsRef<Path>>(path: P) -> Result<Exif, WallflowerError> {
+    let file = File::open(path)?;
+    let data = exif::Reader::new().read_from_container(&mut io::BufReader::new(file))?;
+    let field = |tag| data.get_field(tag, In::PRIMARY).map(|field| &field.value);
 
-        let photo_count = photos.len();
+    let text = |tag| match field(tag) {
+        Some(&Value::Ascii(ref values)) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string()),
+        _ => None,
+    };
+    let rational = |tag| match field(tag) {
+        Some(&Value::Rational(ref values)) => values.first().cloned(),
+        _ => None,
+    };
 
-        for photo in photos {
-            let tx = tx.clone();
-            pool.execute(move || fetch_photo(photo, tx))
+    let camera = match (text(Tag::Make), text(Tag::Model)) {
+        // Models usually include the make, e.g. "Canon EOS 5D Mark II"
+        (Some(ref make), Some(ref model)) if !model.starts_with(make.as_str()) => {
+            Some(format!("{} {}", make, model))
+        }
+        (_, model) => model,
+    };
+    let exposure_time = rational(Tag::ExposureTime).map(|time| {
+        if time.num == 1 {
+            format!("1/{}", time.denom)
+        } else {
+            format!("{}", time.to_f64())
         }
+    });
+
+    Ok(Exif {
+        camera,
+        lens: text(Tag::LensModel),
+        focal_length: rational(Tag::FocalLength).map(|length| length.to_f64()),
+        aperture: rational(Tag::FNumber).map(|aperture| aperture.to_f64()),
+        exposure_time,
+        iso: field(Tag::PhotographicSensitivity).and_then(|value| value.get_uint(0)),
+    })
+}
+
+/// Sync the photos from each of the sources into the cache, then remove the ones that are no
+/// longer in any source or don't fit in the cache
+///
+/// `on_update` is called with the catalog each time it's saved.
+pub fn update_photostream(
+    account: Account,
+    sources: &[Source],
+    downloader: &Downloader,
+    cache: &CacheConfig,
+    on_update: &mut FnMut(&Catalog),
+) -> Result<(), WallflowerError> {
+    // Request list of photos from Flickr
+    // Download the ones that aren't in the cache
+
+    let dir = Path::new("photos");
+    let mut catalog = Catalog::load(dir)?;
+    let synced_at = Utc::now();
+
+    // Every source is listed before downloading so the room in the cache is known
+    let mut listings = Vec::new();
+    for source in sources {
+        println!("listing {:?}", source);
+        let request = source.request();
+        let photos = match account {
+            Account::Public(client) => list_public(client, source, &request)?,
+            Account::Authorized { client, user_id } => {
+                list_authorized(client, user_id, source, &request)?
+            }
+        };
+        listings.push((source.name(), photos));
+    }
+    let listed = listings
+        .iter()
+        .flat_map(|&(_, ref photos)| photos.iter().map(|photo| photo.id.clone()))
+        .collect::<HashSet<_>>();
+    // New photos take the place of ones that have been shown. Once the photos that haven't been
+    // shown fill the cache new photos are skipped, rather than downloaded only to be evicted.
+    let downloader = &if cache.dry_run {
+        downloader.clone()
+    } else {
+        let room = Room::new(&catalog, cache, &ShownLog::load(dir)?, &listed);
+        downloader.with_room(room)
+    };
 
-        rx.iter().take(photo_count).for_each(|result| {
-            if result.is_err() {
-                println!("{:?}", result)
+    for (name, photos) in listings {
+        println!("syncing {}", name);
+        let synced = fetch_photos(account, photos, downloader);
+        for path in synced.paths {
+            if let Some(info) = load_photo_info(&path) {
+                catalog.record(dir, &path, info, Some(&name), synced_at)?;
             }
-        });
+        }
+        // Failed photos aren't cached so they're downloaded again by the next sync
+        for (id, error) in synced.failed {
+            catalog.record_failure(&id, error, synced_at);
+        }
+        if synced.skipped > 0 {
+            println!("{}: the cache is full, skipped {} new photos", name, synced.skipped);
+        }
+        // In case images were deleted by hand
+        catalog.remove_missing(dir);
+        // Saved after each source so the progress isn't lost if a later one fails
+        catalog.save(dir)?;
+        on_update(&catalog);
+    }
+
+    // Only reached if every source was synced, otherwise the photos of a source that failed
+    // would look like they had been removed from Flickr
+    catalog.forget_failures_before(synced_at);
+    let mut cleanup = catalog.plan_cleanup(synced_at, cache, &ShownLog::load(dir)?);
+    cleanup.orphans = catalog.orphans(dir)?;
+    if !cleanup.is_empty() {
+        cleanup.report(cache.dry_run);
+        if !cache.dry_run {
+            catalog.remove(dir, &cleanup)?;
+        }
     }
+    catalog.save(dir)?;
+    on_update(&catalog);
 
     Ok(())
 }
 
-pub fn load_access_token<P: AsRef<Path>>(client: flickr::Client, path: P) -> Result<AuthenticatedClient, WallflowerError> {
-    match File::open(path.as_ref()) {
-        Ok(file) => {
-            let access_token: AccessToken = serde_json::from_reader(file)?;
-            Ok(AuthenticatedClient::new(client, access_token))
+/// Progress of the background sync
+pub enum SyncEvent {
+    /// The catalog was updated, these are the paths of the photos now in it
+    Photos(Vec<PathBuf>),
+    /// A sync failed, it's tried again after the interval unless the access token was rejected
+    Failed(WallflowerError),
+}
+
+/// Who the background sync is for, like `Account` but owned so it can be moved to the thread
+pub enum SyncAccount {
+    Public(flickr::Client),
+    Authorized(Arc<AuthenticatedClient>),
+}
+
+impl SyncAccount {
+    fn update_photostream(
+        &self,
+        sources: &[Source],
+        downloader: &Downloader,
+        cache: &CacheConfig,
+        on_update: &mut FnMut(&Catalog),
+    ) -> Result<(), WallflowerError> {
+        match *self {
+            SyncAccount::Public(ref client) => update_photostream(
+                Account::Public(client),
+                sources,
+                downloader,
+                cache,
+                on_update,
+            ),
+            SyncAccount::Authorized(ref client) => {
+                let user_id = client.user_id()?;
+                let account = Account::Authorized {
+                    client: &**client,
+                    user_id: &user_id,
+                };
+                update_photostream(account, sources, downloader, cache, on_update)
+            }
         }
-        Err(e) => {
-            println!("{:?}", e);
-            let client = client.authenticate()?;
+    }
+}
+
+/// Sync the sources in the background after `delay` and then every `interval`, reporting
+/// changes to the catalog on the returned channel
+///
+/// The thread stops when the receiver is dropped or Flickr rejects the access token.
+pub fn spawn_sync(
+    account: SyncAccount,
+    sources: Vec<Source>,
+    downloader: Downloader,
+    cache: CacheConfig,
+    delay: time::Duration,
+    interval: time::Duration,
+) -> Receiver<SyncEvent> {
+    let (tx, rx) = channel();
 
-            // Save app data for using on the next run.
-            let file = File::create(path.as_ref())?;
-            let _ = serde_json::to_writer_pretty(file, client.access_token())?;
+    thread::spawn(move || {
+        thread::sleep(delay);
+        loop {
+            let mut disconnected = false;
+            let result = account.update_photostream(&sources, &downloader, &cache, &mut |catalog| {
+                let photos = catalog.paths("photos");
+                disconnected = tx.send(SyncEvent::Photos(photos)).is_err();
+            });
+            if let Err(err) = result {
+                let revoked = err.is_invalid_token();
+                if tx.send(SyncEvent::Failed(err)).is_err() || revoked {
+                    return;
+                }
+            }
+            if disconnected {
+                return;
+            }
+
+            thread::sleep(interval);
+        }
+    });
+
+    rx
+}
+
+/// List the photos in a source with only the API key
+fn list_public(
+    client: &flickr::Client,
+    source: &Source,
+    request: &PhotosRequest,
+) -> Result<Vec<Photo>, WallflowerError> {
+    match *source {
+        Source::Photoset { ref id } => collect_pages(
+            client
+                .photoset_photos(id, request)
+                .map(|page| page.map(|page| page.photos)),
+        ),
+        Source::PublicPhotos { ref user_id } => collect_pages(
+            client
+                .public_photos(user_id, request)
+                .map(|page| page.map(|page| page.photos)),
+        ),
+        Source::Interestingness { days } => {
+            let dates = explore_dates(days, Utc::today().naive_utc());
+            let mut photos = Vec::new();
+            // A photo picked on more than one day is only downloaded once
+            let mut seen = HashSet::new();
+            for &date in &dates {
+                let pages = client
+                    .interestingness(Some(date), request)
+                    .map(|page| page.map(|page| page.photos));
+                for photo in collect_pages(pages)? {
+                    if seen.insert(photo.id.clone()) {
+                        photos.push(photo);
+                    }
+                }
+            }
+
+            Ok(photos)
+        }
+        _ => Err(WallflowerError::AuthorizationRequired(format!("{:?}", source))),
+    }
+}
+
+/// List the photos in a source as the authorized user
+fn list_authorized(
+    client: &AuthenticatedClient,
+    user_id: &str,
+    source: &Source,
+    request: &PhotosRequest,
+) -> Result<Vec<Photo>, WallflowerError> {
+    match *source {
+        Source::Photostream => collect_pages(
+            client
+                .photos(user_id, request)
+                .map(|page| page.map(|page| page.photos)),
+        ),
+        Source::Photoset { ref id } => collect_pages(
+            client
+                .photoset_photos(id, user_id, request)
+                .map(|page| page.map(|page| page.photos)),
+        ),
+        Source::Favorites { user_id: ref favorites_of } => {
+            let favorites_of = favorites_of.as_ref().map(String::as_str).unwrap_or(user_id);
+            collect_pages(
+                client
+                    .favorites(favorites_of, request)
+                    .map(|page| page.map(|page| page.photos)),
+            )
+        }
+        Source::Group { ref id } => collect_pages(
+            client
+                .group_photos(id, request)
+                .map(|page| page.map(|page| page.photos)),
+        ),
+        Source::Contacts {
+            just_friends,
+            include_self,
+        } => collect_pages(Some(client.contacts_photos(just_friends, include_self, request))),
+        Source::Search(ref search) => {
+            let mut extras = SIZE_EXTRAS.to_vec();
+            extras.extend_from_slice(&METADATA_EXTRAS);
+            collect_pages(
+                client
+                    .search(&search.clone().extras(&extras))
+                    .map(|page| page.map(|page| page.photos)),
+            )
+        }
+        // Public sources are listed the same way as without authorization
+        Source::PublicPhotos { .. } | Source::Interestingness { .. } => {
+            list_public(client.client(), source, request)
+        }
+    }
+}
+
+/// Request each page in turn, collecting the photos
+fn collect_pages<I>(pages: I) -> Result<Vec<Photo>, WallflowerError>
+where
+    I: IntoIterator<Item = FlickrResult<Vec<Photo>>>,
+{
+    let mut photos = Vec::new();
+    for page in pages {
+        photos.extend(page?);
+    }
+
+    Ok(photos)
+}
+
+/// Download the photos that aren't cached yet and look up any missing EXIF data
+fn fetch_photos(account: Account, photos: Vec<Photo>, downloader: &Downloader) -> Synced {
+    let pool = ThreadPool::new(downloader.workers);
+    let (tx, rx) = channel();
+    let mut synced = Synced::default();
+
+    let photo_count = photos.len();
+    for photo in photos {
+        let tx = tx.clone();
+        let downloader = downloader.clone();
+        pool.execute(move || fetch_photo(photo, downloader, tx))
+    }
+
+    let mut fetched = Vec::new();
+    for (id, result) in rx.iter().take(photo_count) {
+        match result {
+            Ok(result) => fetched.push(result),
+            Err(WallflowerError::CacheFull) => synced.skipped += 1,
+            Err(err) => {
+                println!("{}: {}", id, err);
+                synced.failed.push((id, err.to_string()));
+            }
+        }
+    }
 
-            Ok(client)
+    for (path, needs_exif) in fetched {
+        if needs_exif {
+            if let Err(err) = update_exif(account, &path) {
+                println!("{:?}", err);
+            }
         }
+        synced.paths.push(path);
+    }
+
+    synced
+}
+
+/// The days to sync from Explore, oldest first
+///
+/// Flickr only finishes picking the photos for a day once it's over, so the window ends
+/// yesterday.
+fn explore_dates(days: u32, today: NaiveDate) -> Vec<NaiveDate> {
+    (1..=i64::from(days.max(1)))
+        .rev()
+        .map(|days_ago| today - Duration::days(days_ago))
+        .collect()
+}
+
+/// Add a photo to the authenticated user's album called `title`, creating it if needed
+pub fn add_to_album(client: &AuthenticatedClient, title: &str, photo_id: &str) -> FlickrResult<()> {
+    let photosets = client.photosets(&client.user_id()?)?;
+    match photosets.iter().find(|photoset| photoset.title == title) {
+        Some(photoset) => client.add_to_photoset(&photoset.id, photo_id),
+        None => client.create_photoset(title, photo_id).map(|_id| ()),
     }
 }
 
@@ -185,7 +996,256 @@ fn convert_image_from_srgb_to_linear(img: image::ImageBuffer<Rgba<u8>, Vec<u8>>)
     new_img
 }
 
-pub fn available_photos(dir: &str) -> Result<Vec<PathBuf>, WallflowerError> {
+/// Load the info stored alongside the photo at `path`, if present
+pub fn load_photo_info<P: AsRef<Path>>(path: P) -> Option<PhotoInfo> {
+    File::open(path.as_ref().with_extension("json"))
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+}
+
+fn save_photo_info(path: &Path, info: &PhotoInfo) -> Result<(), WallflowerError> {
+    let file = File::create(path.with_extension("json"))?;
+    serde_json::to_writer(file, info)?;
+    Ok(())
+}
+
+#[test]
+fn test_best_size() {
+    let size = |suffix, width, height| PhotoSize {
+        suffix,
+        url: format!("https://farm1.staticflickr.com/1/1_a_{}.jpg", suffix)
+            .parse()
+            .unwrap(),
+        width,
+        height,
+    };
+    let sizes = [
+        size("c", 800, 533),
+        size("l", 1024, 683),
+        size("h", 1600, 1067),
+        size("k", 2048, 1365),
+    ];
+    let display = Size {
+        width: 1366,
+        height: 768,
+    };
+
+    assert_eq!(best_size(&sizes, display).map(|size| size.suffix), Some("h"));
+    assert_eq!(best_size(&sizes[..2], display).map(|size| size.suffix), Some("l"));
+    assert!(best_size(&[], display).is_none());
+}
+
+#[test]
+fn test_transfer_time() {
+    assert_eq!(transfer_time(1500, 1000), time::Duration::from_millis(1500));
+    assert_eq!(transfer_time(16 * 1024, 64 * 1024), time::Duration::from_millis(250));
+    assert_eq!(transfer_time(0, 1000), time::Duration::from_secs(0));
+}
+
+#[test]
+fn test_copy_throttled() {
+    let data = vec![7; 40 * 1024];
+    let mut copy = Vec::new();
+    let copied = copy_throttled(&mut &data[..], &mut copy, None).unwrap();
+    assert_eq!(copied, data.len() as u64);
+    assert_eq!(copy, data);
+}
+
+/// Serve `responses` to one connection each on a local port, returning the requests received
+#[cfg(test)]
+fn serve(responses: Vec<Vec<u8>>) -> (Url, thread::JoinHandle<Vec<String>>) {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/1_a_h.jpg", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let server = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut byte = [0; 1];
+            while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                request.push(byte[0]);
+            }
+            requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            stream.write_all(&response).unwrap();
+        }
+        requests
+    });
+
+    (url, server)
+}
+
+#[cfg(test)]
+fn response(status: &str, headers: &[&str], body: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status).into_bytes();
+    for header in headers {
+        response.extend(header.as_bytes());
+        response.extend(b"\r\n");
+    }
+    response.extend(b"\r\n");
+    response.extend(body);
+    response
+}
+
+#[cfg(test)]
+fn test_downloader() -> Downloader {
+    let display = Size {
+        width: 1366,
+        height: 768,
+    };
+    Downloader::new(reqwest::Client::new(), display, &DownloadConfig::default())
+}
+
+#[cfg(test)]
+fn test_jpeg() -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    image::jpeg::JPEGEncoder::new(&mut jpeg)
+        .encode(&[128; 8 * 8 * 3], 8, 8, image::ColorType::RGB(8))
+        .unwrap();
+    jpeg
+}
+
+#[test]
+fn test_download_file() {
+    let dir = ::std::env::temp_dir().join(format!("wallflower-download-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("1_a_h.jpg");
+    let part = dir.join("1_a_h.jpg.part");
+    let jpeg = test_jpeg();
+    let length = format!("Content-Length: {}", jpeg.len());
+    let (url, server) = serve(vec![
+        response("200 OK", &["Content-Type: image/jpeg", &length], &jpeg),
+        response("200 OK", &["Content-Type: image/jpeg", "Content-Length: 8"], b"not jpeg"),
+    ]);
+
+    download_file(&test_downloader(), &url, &path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), jpeg);
+    assert!(!part.exists());
+
+    // An image content type isn't enough, the body has to decode
+    fs::remove_file(&path).unwrap();
+    match download_file(&test_downloader(), &url, &path) {
+        Err(WallflowerError::InvalidDownload { .. }) => (),
+        other => panic!("expected InvalidDownload, got {:?}", other),
+    }
+    assert!(!path.exists());
+    assert!(!part.exists());
+
+    server.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_download_room() {
+    let dir = ::std::env::temp_dir().join(format!("wallflower-room-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("1_a_h.jpg");
+    let jpeg = test_jpeg();
+    // Without a Content-Length the size is only known once the body has been received
+    let (url, server) = serve(vec![response("200 OK", &["Content-Type: image/jpeg"], &jpeg)]);
+    let config = CacheConfig {
+        max_bytes: Some(jpeg.len() as u64 - 1),
+        ..Default::default()
+    };
+    let room = Room::new(&Catalog::default(), &config, &ShownLog::default(), &HashSet::new());
+    let downloader = test_downloader().with_room(room);
+
+    match download_file(&downloader, &url, &path) {
+        Err(WallflowerError::CacheFull) => (),
+        other => panic!("expected CacheFull, got {:?}", other),
+    }
+    assert!(!path.exists());
+    assert!(!dir.join("1_a_h.jpg.part").exists());
+    // The room set aside before the body was received is given back
+    assert!(downloader.take_room(1, jpeg.len() as u64 - 1));
+
+    server.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_resume_download() {
+    let dir = ::std::env::temp_dir().join(format!("wallflower-resume-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("1_a_h.jpg");
+    let part = dir.join("1_a_h.jpg.part");
+    let jpeg = test_jpeg();
+    let half = jpeg.len() / 2;
+    let length = format!("Content-Length: {}", jpeg.len());
+    let rest = format!("Content-Length: {}", jpeg.len() - half);
+    let range = format!("Content-Range: bytes {}-{}/{}", half, jpeg.len() - 1, jpeg.len());
+    let (url, server) = serve(vec![
+        // Cut short, then resumed
+        response("200 OK", &["Content-Type: image/jpeg", &length], &jpeg[..half]),
+        response(
+            "206 Partial Content",
+            &["Content-Type: image/jpeg", &rest, &range],
+            &jpeg[half..],
+        ),
+        // Resumed by a server that doesn't support ranges
+        response("200 OK", &["Content-Type: image/jpeg", &length], &jpeg),
+        // Resumed from the wrong place
+        response(
+            "206 Partial Content",
+            &["Content-Type: image/jpeg", &rest, "Content-Range: bytes 0-3/8"],
+            &jpeg[half..],
+        ),
+        // Resumed past the end
+        response("416 Range Not Satisfiable", &["Content-Length: 0"], b""),
+    ]);
+    let downloader = test_downloader();
+
+    assert!(download_file(&downloader, &url, &path).is_err());
+    assert!(!path.exists());
+    assert_eq!(fs::read(&part).unwrap(), &jpeg[..half]);
+    download_file(&downloader, &url, &path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), jpeg);
+
+    // The part is either replaced by the whole image or discarded
+    for &(status, ok) in &[("200", true), ("206", false), ("416", false)] {
+        let _ = fs::remove_file(&path);
+        fs::write(&part, &jpeg[..half]).unwrap();
+        let result = download_file(&downloader, &url, &path);
+        assert_eq!(result.is_ok(), ok, "{}", status);
+        assert_eq!(path.exists(), ok, "{}", status);
+        assert!(!part.exists(), "{}", status);
+    }
+
+    let requests = server.join().unwrap();
+    let resumed_from = format!("range: bytes={}-", half);
+    assert!(!requests[0].contains("range:"));
+    assert!(requests[1..].iter().all(|request| request.contains(&resumed_from)));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_throttle() {
+    let start = Instant::now();
+    let throttle = Throttle::new(100 * 1024);
+    let data = vec![7; 30 * 1024];
+    let copied = copy_throttled(&mut &data[..], &mut io::sink(), Some(&throttle)).unwrap();
+    assert_eq!(copied, data.len() as u64);
+    assert!(start.elapsed() >= time::Duration::from_millis(300));
+}
+
+#[test]
+fn test_explore_dates() {
+    let today = NaiveDate::from_ymd(2018, 9, 1);
+    assert_eq!(
+        explore_dates(3, today),
+        vec![
+            NaiveDate::from_ymd(2018, 8, 29),
+            NaiveDate::from_ymd(2018, 8, 30),
+            NaiveDate::from_ymd(2018, 8, 31),
+        ]
+    );
+    assert_eq!(explore_dates(0, today), vec![NaiveDate::from_ymd(2018, 8, 31)]);
+}
+
+pub fn available_photos<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, WallflowerError> {
     let mut photos = vec![];
     let jpg = OsStr::new("jpg");
 
@@ -1,3 +1,5 @@
+use flickr::{self, Exif};
+use slideshow::PhotoInfo;
 use weather::Observation;
 
 pub fn latest_observation(observations: Vec<Observation>) -> Option<Observation> {
@@ -19,3 +21,53 @@ pub fn format_observation(observation: &Option<Observation>) -> String {
     }
 }
 
+
+/// Describe a photo with its title, owner, the date it was taken and its license
+pub fn format_caption(info: &Option<PhotoInfo>) -> String {
+    let info = match *info {
+        Some(ref info) => info,
+        None => return String::new(),
+    };
+
+    let mut caption = info.title.clone();
+    if let Some(ref owner_name) = info.owner_name {
+        caption.push_str(&format!(" by {}", owner_name));
+    }
+    if let Some(date_taken) = info.date_taken {
+        caption.push_str(&format!(", {}", date_taken.format("%-d %B %Y")));
+    }
+    if let Some(license) = info.license.and_then(flickr::license_name) {
+        caption.push_str(&format!(" ({})", license));
+    }
+    if let Some(ref exif) = info.exif {
+        let settings = format_exif(exif);
+        if !settings.is_empty() {
+            caption.push_str("   ");
+            caption.push_str(&settings);
+        }
+    }
+
+    caption
+}
+
+/// Summarise camera settings, e.g. "Canon EOS 5D Mark II  24mm  f/4  1/60s  ISO 400"
+pub fn format_exif(exif: &Exif) -> String {
+    let mut settings = Vec::new();
+    if let Some(ref camera) = exif.camera {
+        settings.push(camera.clone());
+    }
+    if let Some(focal_length) = exif.focal_length {
+        settings.push(format!("{}mm", focal_length));
+    }
+    if let Some(aperture) = exif.aperture {
+        settings.push(format!("f/{}", aperture));
+    }
+    if let Some(ref exposure_time) = exif.exposure_time {
+        settings.push(format!("{}s", exposure_time));
+    }
+    if let Some(iso) = exif.iso {
+        settings.push(format!("ISO {}", iso));
+    }
+
+    settings.join("  ")
+}
@@ -0,0 +1,300 @@
+extern crate base64;
+extern crate ring;
+
+use self::ring::aead::{self, OpeningKey, SealingKey, CHACHA20_POLY1305};
+use self::ring::rand::{SecureRandom, SystemRandom};
+use serde_json;
+
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flickr::AccessToken;
+use WallflowerError;
+
+/// Environment variable holding the base64 encoded key used to encrypt the access token
+pub const KEY_VAR: &str = "WALLFLOWER_TOKEN_KEY";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+// {"nonce":"...","ciphertext":"..."} with both values base64 encoded
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedToken {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StoredToken {
+    Encrypted(EncryptedToken),
+    Plain(AccessToken),
+}
+
+/// Where the access token is saved, and the key to encrypt it with
+///
+/// The token file is only readable by the current user. With a key the token is also
+/// encrypted with ChaCha20-Poly1305.
+#[derive(Clone)]
+pub struct TokenStore {
+    path: PathBuf,
+    key: Option<Vec<u8>>,
+}
+
+impl TokenStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        TokenStore {
+            path: path.into(),
+            key: None,
+        }
+    }
+
+    /// Encrypt the token with `key`, which must be 32 bytes
+    pub fn key(mut self, key: Vec<u8>) -> Result<Self, WallflowerError> {
+        if key.len() != KEY_LEN {
+            return Err(WallflowerError::InvalidTokenKey);
+        }
+        self.key = Some(key);
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the saved access token, if the app has been authorized
+    ///
+    /// Prints a warning if the file can be read by other users. A token saved in plain text
+    /// before a key was configured is encrypted straight away.
+    pub fn load(&self) -> Result<Option<AccessToken>, WallflowerError> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        warn_if_readable_by_others(&self.path, &file);
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        match serde_json::from_slice(&contents)? {
+            StoredToken::Plain(access_token) => {
+                if self.key.is_some() {
+                    self.save(&access_token)?;
+                    println!("Encrypted the access token in {}", self.path.display());
+                }
+                Ok(Some(access_token))
+            }
+            StoredToken::Encrypted(encrypted) => {
+                let key = self.key.as_ref().ok_or(WallflowerError::TokenKeyMissing)?;
+                let plaintext = decrypt(key, &encrypted)?;
+                Ok(Some(serde_json::from_slice(&plaintext)?))
+            }
+        }
+    }
+
+    /// Save the access token for using on the next run
+    pub fn save(&self, access_token: &AccessToken) -> Result<(), WallflowerError> {
+        let contents = match self.key {
+            Some(ref key) => {
+                let encrypted = encrypt(key, &serde_json::to_vec(access_token)?)?;
+                serde_json::to_vec_pretty(&encrypted)?
+            }
+            None => serde_json::to_vec_pretty(access_token)?,
+        };
+
+        write_private(&self.path, &contents)?;
+        Ok(())
+    }
+
+    /// Delete the saved access token, if there is one
+    pub fn remove(&self) -> Result<(), WallflowerError> {
+        match fs::remove_file(&self.path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(WallflowerError::from),
+        }
+    }
+
+    /// Move a token saved in plain text at `legacy` by older versions into this store
+    pub fn migrate<P: AsRef<Path>>(&self, legacy: P) -> Result<(), WallflowerError> {
+        let legacy = TokenStore::new(legacy.as_ref());
+        if self.path.exists() {
+            return Ok(());
+        }
+
+        if let Some(access_token) = legacy.load()? {
+            self.save(&access_token)?;
+            legacy.remove()?;
+            println!(
+                "Moved the access token from {} to {}",
+                legacy.path.display(),
+                self.path.display()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/wallflower/flickr-token.json`, falling back to `~/.config` if
+/// `XDG_CONFIG_HOME` is not set
+pub fn default_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("wallflower").join("flickr-token.json"))
+}
+
+/// Read the base64 encoded key from `WALLFLOWER_TOKEN_KEY`, or `key_file` if it is not set
+pub fn load_key(key_file: Option<&Path>) -> Result<Option<Vec<u8>>, WallflowerError> {
+    let encoded = match env::var(KEY_VAR) {
+        Ok(key) => key,
+        Err(_) => match key_file {
+            Some(key_file) => {
+                let mut key = String::new();
+                File::open(key_file)?.read_to_string(&mut key)?;
+                key
+            }
+            None => return Ok(None),
+        },
+    };
+
+    base64::decode(encoded.trim())
+        .map(Some)
+        .map_err(|_| WallflowerError::InvalidTokenKey)
+}
+
+fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<EncryptedToken, WallflowerError> {
+    let sealing_key =
+        SealingKey::new(&CHACHA20_POLY1305, key).map_err(|_| WallflowerError::InvalidTokenKey)?;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "unable to generate a nonce"))?;
+
+    let tag_len = CHACHA20_POLY1305.tag_len();
+    let mut in_out = plaintext.to_vec();
+    in_out.resize(plaintext.len() + tag_len, 0);
+    let len = aead::seal_in_place(&sealing_key, &nonce, &[], &mut in_out, tag_len)
+        .map_err(|_| WallflowerError::InvalidTokenKey)?;
+    in_out.truncate(len);
+
+    Ok(EncryptedToken {
+        nonce: base64::encode(&nonce),
+        ciphertext: base64::encode(&in_out),
+    })
+}
+
+fn decrypt(key: &[u8], encrypted: &EncryptedToken) -> Result<Vec<u8>, WallflowerError> {
+    let opening_key =
+        OpeningKey::new(&CHACHA20_POLY1305, key).map_err(|_| WallflowerError::InvalidTokenKey)?;
+    let nonce =
+        base64::decode(&encrypted.nonce).map_err(|_| WallflowerError::TokenDecryptError)?;
+    let mut in_out =
+        base64::decode(&encrypted.ciphertext).map_err(|_| WallflowerError::TokenDecryptError)?;
+
+    let plaintext = aead::open_in_place(&opening_key, &nonce, &[], 0, &mut in_out)
+        .map_err(|_| WallflowerError::TokenDecryptError)?;
+    Ok(plaintext.to_vec())
+}
+
+/// Write `contents` to `path` so that only the current user can read it
+///
+/// The file is written next to `path` and moved into place so an interrupted write doesn't
+/// lose the existing token.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            let mut builder = DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::DirBuilderExt;
+                builder.mode(0o700);
+            }
+            builder.create(dir)?;
+        }
+    }
+
+    // The mode is only applied when the file is created
+    let tmp = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(unix)]
+fn warn_if_readable_by_others(path: &Path, file: &File) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = file.metadata() {
+        if metadata.permissions().mode() & 0o077 != 0 {
+            println!(
+                "Warning: {} can be read by other users, run chmod 600 on it",
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_readable_by_others(_path: &Path, _file: &File) {}
+
+#[test]
+fn test_encrypted_round_trip() {
+    let dir = env::temp_dir().join(format!("wallflower-token-{}", ::std::process::id()));
+    let path = dir.join("flickr-token.json");
+    let token: AccessToken = serde_json::from_str(
+        r#"{"token":"72157626318069415-087bfc7b5816092c","secret":"a202d1f853ec69de"}"#,
+    ).unwrap();
+
+    let store = TokenStore::new(&path).key(vec![7; KEY_LEN]).unwrap();
+    store.save(&token).unwrap();
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert!(!contents.contains("a202d1f853ec69de"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let loaded = store.load().unwrap().unwrap();
+    assert_eq!(
+        serde_json::to_value(&loaded).unwrap(),
+        serde_json::to_value(&token).unwrap()
+    );
+
+    let wrong_key = TokenStore::new(&path).key(vec![8; KEY_LEN]).unwrap();
+    match wrong_key.load() {
+        Err(WallflowerError::TokenDecryptError) => (),
+        other => panic!("expected TokenDecryptError, got {:?}", other),
+    }
+
+    // A token saved before the key was added is encrypted when it's loaded
+    TokenStore::new(&path).save(&token).unwrap();
+    assert!(store.load().unwrap().is_some());
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert!(!contents.contains("a202d1f853ec69de"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
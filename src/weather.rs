@@ -2,7 +2,20 @@ extern crate ftp;
 extern crate reqwest;
 extern crate serde_json;
 
-pub struct Client;
+use self::reqwest::Url;
+
+use std::io;
+use std::sync::Arc;
+
+use http::{Request, Transport, TransportError};
+
+/// Observations for Melbourne (Olympic Park)
+pub const OBSERVATIONS_URL: &str = "http://reg.bom.gov.au/fwo/IDV60901/IDV60901.95936.json";
+
+pub struct Client {
+    transport: Arc<Transport>,
+    observations_url: String,
+}
 
 #[derive(Debug, Deserialize)]
 struct ObservationsRaw {
@@ -66,9 +79,10 @@ pub struct Forecast;
 
 #[derive(Fail, Debug)]
 pub enum WeatherError {
-    // #[fail(display = "I/O error")]
-    // IoError(io::Error),
+    #[fail(display = "I/O error")] IoError(io::Error),
     #[fail(display = "HTTP error")] HttpError(reqwest::Error),
+    #[fail(display = "URL error")] UrlError(reqwest::UrlError),
+    #[fail(display = "HTTP status {}", _0)] HttpStatus(u16),
     // #[fail(display = "UTF-8 parse error")]
     // ParseError(str::Utf8Error),
     #[fail(display = "JSON error")] JsonError(serde_json::Error),
@@ -88,12 +102,41 @@ impl From<reqwest::Error> for WeatherError {
     }
 }
 
+impl From<reqwest::UrlError> for WeatherError {
+    fn from(err: reqwest::UrlError) -> Self {
+        WeatherError::UrlError(err)
+    }
+}
+
+impl From<TransportError> for WeatherError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            TransportError::HttpError(err) => WeatherError::HttpError(err),
+            TransportError::IoError(err) => WeatherError::IoError(err),
+        }
+    }
+}
+
 //ftp://ftp.bom.gov.au/anon/gen/fwo/IDV10450.xml
 //http://reg.bom.gov.au/fwo/IDV60901/IDV60901.95936.json
 
 impl Client {
-    pub fn new() -> Self {
-        Client {}
+    pub fn new(http: reqwest::Client) -> Self {
+        Client::with_transport(Arc::new(http))
+    }
+
+    /// Send requests through `transport` rather than directly over HTTP
+    pub fn with_transport(transport: Arc<Transport>) -> Self {
+        Client {
+            transport,
+            observations_url: String::from(OBSERVATIONS_URL),
+        }
+    }
+
+    /// Fetch observations from the BoM JSON feed at `url` instead of Melbourne's
+    pub fn observations_url(mut self, url: &str) -> Self {
+        self.observations_url = url.to_string();
+        self
     }
 
     pub fn forecast(&self) -> WeatherResult<Forecast> {
@@ -101,9 +144,25 @@ impl Client {
     }
 
     pub fn observations(&self) -> WeatherResult<Vec<Observation>> {
-        let obs: ObservationsRaw =
-            reqwest::get("http://reg.bom.gov.au/fwo/IDV60901/IDV60901.95936.json")?.json()?;
+        let url = Url::parse(&self.observations_url)?;
+        let res = self.transport.send(Request::get(url))?;
+        if !res.is_success() {
+            return Err(WeatherError::HttpStatus(res.status));
+        }
 
+        let obs: ObservationsRaw = serde_json::from_slice(&res.body)?;
         Ok(obs.observations.data)
     }
 }
+
+#[test]
+fn test_observations_transport() {
+    let body = r#"{"observations":{"notice":[],"header":[],"data":[{"sort_order":0,"wmo":95936,"name":"Melbourne (Olympic Park)","history_product":"IDV60901","local_date_time":"11/01:30pm","local_date_time_full":"20180811133000","aifstime_utc":"20180811033000","lat":-37.8,"lon":145.0,"apparent_t":8.4,"cloud":"-","delta_t":2.9,"gust_kmh":33,"gust_kt":18,"air_temp":12.6,"dewpt":4.6,"press":1012.3,"press_qnh":1012.3,"press_msl":1012.3,"press_tend":"-","rain_trace":"0.2","rel_hum":58,"wind_dir":"NNW","wind_spd_kmh":22,"wind_spd_kt":12}]}}"#;
+    let transport = Arc::new(::http::FixtureTransport::new(&[(200, body)]));
+    let client = Client::with_transport(transport.clone()).observations_url("http://localhost/obs.json");
+
+    let observations = client.observations().unwrap();
+    assert_eq!(observations.len(), 1);
+    assert_eq!(observations[0].name, "Melbourne (Olympic Park)");
+    assert_eq!(transport.requests.lock().unwrap()[0].url.path(), "/obs.json");
+}